@@ -0,0 +1,139 @@
+//! Times contributing to and verifying the parameters of each MASP circuit.
+//!
+//! Run with `cargo bench --features verification`, optionally followed by
+//! `-- <circuit>` to only measure one circuit. Creating the initial
+//! parameters is not measured, but it takes a while for every circuit
+//! selected.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use masp_mpc::batch;
+use masp_mpc::circuits::{self, MaspCircuit};
+use masp_mpc::layout::{self, Encoding, Limits};
+use masp_phase2::{verify_contribution, MPCParameters};
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+
+/// Parameters of one circuit, created the first time a benchmark needs them.
+struct Fixture {
+    name: &'static str,
+    prepared: Option<Prepared>,
+}
+
+struct Prepared {
+    /// Initial parameters.
+    before: MPCParameters,
+    /// `before` with one contribution.
+    after: MPCParameters,
+    /// `after`, serialized.
+    bytes: Vec<u8>,
+    /// `after`, serialized with compressed queries.
+    compressed: Vec<u8>,
+}
+
+impl Fixture {
+    fn get(&mut self) -> &Prepared {
+        let name = self.name;
+        self.prepared.get_or_insert_with(|| {
+            let before = MPCParameters::new(MaspCircuit::blank(name).unwrap()).unwrap();
+            let mut after = before.clone();
+            after.contribute(&mut ChaChaRng::seed_from_u64(0), &0);
+
+            let mut bytes = vec![];
+            after.write(&mut bytes).unwrap();
+            let mut compressed = vec![];
+            layout::write_encoded_params(&after, &mut compressed, Encoding::Compressed).unwrap();
+
+            Prepared {
+                before,
+                after,
+                bytes,
+                compressed,
+            }
+        })
+    }
+}
+
+fn ceremony(c: &mut Criterion) {
+    for &name in circuits::MASP_CIRCUITS {
+        let mut fixture = Fixture {
+            name,
+            prepared: None,
+        };
+
+        let mut group = c.benchmark_group(name);
+        group.sample_size(10);
+
+        group.bench_function("contribute", |b| {
+            let prepared = fixture.get();
+            let mut rng = ChaChaRng::seed_from_u64(1);
+            b.iter_batched(
+                || prepared.before.clone(),
+                |mut params| params.contribute(&mut rng, &0),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function("verify_contribution", |b| {
+            let prepared = fixture.get();
+            b.iter(|| verify_contribution(&prepared.before, &prepared.after).unwrap())
+        });
+        group.bench_function("verify", |b| {
+            let prepared = fixture.get();
+            b.iter(|| {
+                prepared
+                    .after
+                    .verify(MaspCircuit::blank(name).unwrap())
+                    .unwrap()
+            })
+        });
+        group.bench_function("verify_batch", |b| {
+            let prepared = fixture.get();
+            b.iter(|| batch::verify(&prepared.after, MaspCircuit::blank(name).unwrap()).unwrap())
+        });
+        group.bench_function("write", |b| {
+            let prepared = fixture.get();
+            b.iter(|| {
+                let mut bytes = Vec::with_capacity(prepared.bytes.len());
+                prepared.after.write(&mut bytes).unwrap();
+                bytes
+            })
+        });
+        group.bench_function("read", |b| {
+            let prepared = fixture.get();
+            b.iter(|| MPCParameters::read(&prepared.bytes[..], false).unwrap())
+        });
+        group.bench_function("read_checked", |b| {
+            let prepared = fixture.get();
+            b.iter(|| MPCParameters::read(&prepared.bytes[..], true).unwrap())
+        });
+        group.bench_function("read_checked_parallel", |b| {
+            let prepared = fixture.get();
+            b.iter(|| layout::read_params(&prepared.bytes[..], true, Limits::any()).unwrap())
+        });
+        group.bench_function("write_compressed", |b| {
+            let prepared = fixture.get();
+            b.iter(|| {
+                let mut bytes = Vec::with_capacity(prepared.compressed.len());
+                layout::write_encoded_params(&prepared.after, &mut bytes, Encoding::Compressed)
+                    .unwrap();
+                bytes
+            })
+        });
+        group.bench_function("read_compressed", |b| {
+            let prepared = fixture.get();
+            b.iter(|| {
+                layout::read_encoded_params(
+                    &prepared.compressed[..],
+                    false,
+                    Limits::any(),
+                    Encoding::Compressed,
+                )
+                .unwrap()
+            })
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, ceremony);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use masp_mpc::ceremony::{contribution_hashes, Ceremony, UnknownCircuits, MAGIC};
+use masp_mpc::layout::{self, FileLayout};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let scanned = FileLayout::scan(Cursor::new(data));
+
+    let mut reader = Cursor::new(data);
+    let ceremony = match Ceremony::read(&mut reader, false, UnknownCircuits::Allow) {
+        Ok(ceremony) => ceremony,
+        Err(_) => return,
+    };
+    let len = reader.position();
+
+    let mut written = vec![];
+    ceremony.write(&mut written).unwrap();
+    assert_eq!(ceremony.serialized_len(), written.len() as u64);
+
+    // Legacy headers are written in the current format instead
+    if data.starts_with(&MAGIC) {
+        assert_eq!(written[..], data[..len as usize]);
+    }
+    let reread = Ceremony::read(&written[..], false, UnknownCircuits::Allow).unwrap();
+    assert_eq!(reread.header(), ceremony.header());
+    assert_eq!(reread.contributions(), ceremony.contributions());
+
+    // Only whole files can be scanned
+    if len != data.len() as u64 {
+        return;
+    }
+    let scanned = scanned.unwrap();
+    assert_eq!(scanned.header, ceremony.header());
+    for (layout, circuit) in scanned.circuits.iter().zip(&ceremony.circuits) {
+        assert_eq!(
+            layout.end() - layout.start,
+            layout::encoded_len(&circuit.params, ceremony.encoding)
+        );
+        // The public keys end the parameters
+        assert_eq!(
+            layout.contributions.len,
+            contribution_hashes(&circuit.params).len()
+        );
+        assert_eq!(layout.contributions.end(), layout.end());
+    }
+});
@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use masp_mpc::ceremony::{Header, MAGIC};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = Cursor::new(data);
+    let header = match Header::read(&mut reader) {
+        Ok(header) => header,
+        Err(_) => return,
+    };
+
+    let mut written = vec![];
+    header.write(&mut written).unwrap();
+    assert_eq!(Header::read(&written[..]).unwrap(), header);
+
+    // Legacy headers are written in the current format instead
+    if data.starts_with(&MAGIC) {
+        assert_eq!(written[..], data[..reader.position() as usize]);
+    }
+});
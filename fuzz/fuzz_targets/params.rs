@@ -0,0 +1,47 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use masp_mpc::layout::{self, Encoding, Layout, Limits};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    // Locating the sections must not depend on the points being valid
+    let scanned = Layout::scan(Cursor::new(data));
+
+    let mut reader = Cursor::new(data);
+    let params = match layout::read_params(&mut reader, false, Limits::any()) {
+        Ok(params) => params,
+        Err(_) => {
+            assert!(layout::read_params(data, true, Limits::any()).is_err());
+            return;
+        }
+    };
+    let len = reader.position();
+
+    let mut written = vec![];
+    params.write(&mut written).unwrap();
+    assert_eq!(written[..], data[..len as usize]);
+    assert_eq!(layout::serialized_len(&params), len);
+
+    let layout = scanned.unwrap();
+    assert_eq!(layout.end(), len);
+    assert_eq!(layout.contributions.len, params.contributions.len());
+    assert_eq!(layout.contributions.end(), len);
+
+    // Points off the curve can't be compressed, and fail the checks anyway
+    let mut compressed = vec![];
+    if layout::write_encoded_params(&params, &mut compressed, Encoding::Compressed).is_err() {
+        assert!(layout::read_params(data, true, Limits::any()).is_err());
+        return;
+    }
+    assert_eq!(
+        layout::encoded_len(&params, Encoding::Compressed),
+        compressed.len() as u64
+    );
+    let decompressed =
+        layout::read_encoded_params(&compressed[..], false, Limits::any(), Encoding::Compressed)
+            .unwrap();
+    let mut rewritten = vec![];
+    decompressed.write(&mut rewritten).unwrap();
+    assert_eq!(rewritten, written);
+});
@@ -0,0 +1,413 @@
+//! Batched verification of `MPCParameters`.
+//!
+//! `MPCParameters::verify` checks two same-ratio equations `e(a, d) = e(b, c)`
+//! for every contribution and three more for the final parameters, each with
+//! its own pairings. Here every equation is scaled by a random scalar and
+//! moved to one side, so that, except with negligible probability, all of
+//! them hold exactly when the product of all the pairings is one. Terms with
+//! the same G2 element are merged first, which leaves two Miller loops per
+//! contribution, two for the final parameters and a single final
+//! exponentiation per circuit.
+
+use crate::layout::{groth16_len, G1_LEN, G2_LEN, PUBKEY_LEN};
+use crate::progress::{Mode, Progress, Unit};
+use bellman::Circuit;
+use blake2::{Blake2b512, Digest};
+use bls12_381::{
+    multi_miller_loop, G1Affine, G1Projective, G2Affine, G2Prepared, G2Projective, Gt, Scalar,
+};
+use group::{Group, Wnaf};
+use masp_phase2::MPCParameters;
+use rand::rngs::OsRng;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Stage of [`verify_with`], reported as it starts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    /// Creating the initial parameters from the circuit.
+    Setup,
+    /// Checking the transcript of every contribution.
+    Contributions(usize),
+    /// Combining the H or L query, reported as it starts, as it goes and once
+    /// it's done.
+    Query {
+        name: &'static str,
+        done: u64,
+        total: u64,
+    },
+    /// The final pairing check.
+    Pairing,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stage::Setup => write!(f, "creating initial parameters"),
+            Stage::Contributions(n) => write!(f, "checking {} contributions", n),
+            Stage::Query { name, done, total } => {
+                write!(f, "combining {} query, {} of {} points", name, done, total)
+            }
+            Stage::Pairing => write!(f, "checking pairings"),
+        }
+    }
+}
+
+/// Returns a callback for [`verify_with`] reporting its stages for the circuit
+/// `label`: the queries as progress in points, the other stages as messages.
+pub fn reporter(mode: Mode, label: String) -> impl FnMut(Stage) {
+    let mut query: Option<Progress> = None;
+    move |stage| match stage {
+        Stage::Query { done, total, .. } if done == total => {
+            if let Some(mut progress) = query.take() {
+                progress.finish();
+            }
+        }
+        Stage::Query { name, done, total } => query
+            .get_or_insert_with(|| {
+                let task = format!("{}: combining {} query", label, name);
+                Progress::new(mode, task, Unit::Points, total)
+            })
+            .set(done),
+        stage => mode.message(&label, &stage.to_string()),
+    }
+}
+
+/// Number of threads to use by default: `BELLMAN_NUM_CPUS` if set, like
+/// bellman itself, or else the number of cores.
+pub fn default_threads() -> usize {
+    std::env::var("BELLMAN_NUM_CPUS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+/// Points per second `MPCParameters::contribute` gets through on `threads`
+/// threads, timed by exponentiating a few G1 points the way it does. It
+/// reports no progress of its own, so this is what estimates it.
+pub fn contribution_rate(threads: usize) -> f64 {
+    const SAMPLE: usize = 64;
+    let threads = threads.max(1);
+    let coeff = random_scalar(&mut OsRng);
+    let began = Instant::now();
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                let mut wnaf = Wnaf::new();
+                let mut point = G1Projective::generator();
+                for _ in 0..SAMPLE {
+                    point = wnaf.base(point, 1).scalar(&coeff);
+                }
+                G1Affine::from(point)
+            });
+        }
+    });
+    (threads * SAMPLE) as f64 / began.elapsed().as_secs_f64().max(1e-9)
+}
+
+/// Verifies `params` against `circuit`, accepting and rejecting the same
+/// parameters as `MPCParameters::verify` and returning the same contribution
+/// hashes.
+#[allow(clippy::result_unit_err)]
+pub fn verify<C: Circuit<Scalar>>(params: &MPCParameters, circuit: C) -> Result<Vec<[u8; 64]>, ()> {
+    verify_with(params, circuit, default_threads(), |_| {})
+}
+
+/// Same as [`verify`], combining the queries on up to `threads` threads and
+/// calling `progress` as each stage starts.
+#[allow(clippy::result_unit_err)]
+pub fn verify_with<C: Circuit<Scalar>, F: FnMut(Stage)>(
+    params: &MPCParameters,
+    circuit: C,
+    threads: usize,
+    mut progress: F,
+) -> Result<Vec<[u8; 64]>, ()> {
+    progress(Stage::Setup);
+    let initial_params = MPCParameters::new(circuit).map_err(|_| ())?;
+    let i = initial_params.get_params();
+    let p = params.get_params();
+
+    // Contributions only change delta, H and L
+    if i.h.len() != p.h.len()
+        || i.l.len() != p.l.len()
+        || i.a != p.a
+        || i.b_g1 != p.b_g1
+        || i.b_g2 != p.b_g2
+        || i.vk.alpha_g1 != p.vk.alpha_g1
+        || i.vk.beta_g1 != p.vk.beta_g1
+        || i.vk.beta_g2 != p.vk.beta_g2
+        || i.vk.gamma_g2 != p.vk.gamma_g2
+        || i.vk.ic != p.vk.ic
+    {
+        return Err(());
+    }
+    let initial_cs_hash = cs_hash(&initial_params);
+    if initial_cs_hash[..] != cs_hash(params)[..] {
+        return Err(());
+    }
+
+    let mut rng = ChaChaRng::from_rng(OsRng).expect("couldn't seed RNG");
+    let mut terms = vec![];
+
+    let mut transcript = Blake2b512::new();
+    transcript.update(&initial_cs_hash[..]);
+    let mut current_delta = G1Affine::generator();
+    let mut result = vec![];
+    progress(Stage::Contributions(params.contributions.len()));
+    for pubkey in &params.contributions {
+        let mut bytes = Vec::with_capacity(PUBKEY_LEN);
+        pubkey
+            .write(&mut bytes)
+            .expect("writing to a Vec can't fail");
+        let pubkey = PublicKey::parse(&bytes).ok_or(())?;
+
+        // The transcript covers everything before this contribution, then s
+        // and s_delta
+        let mut ours = transcript.clone();
+        ours.update(&bytes[G1_LEN..3 * G1_LEN]);
+        if ours.finalize()[..] != pubkey.transcript[..] {
+            return Err(());
+        }
+        transcript.update(&bytes);
+
+        // e(s, r_delta) = e(s_delta, r) and
+        // e(current_delta, r_delta) = e(delta_after, r)
+        let r = G2Affine::from(hash_to_g2(&pubkey.transcript));
+        let x = random_scalar(&mut rng);
+        let y = random_scalar(&mut rng);
+        terms.push((
+            pubkey.s * x + current_delta * y,
+            G2Prepared::from(pubkey.r_delta),
+        ));
+        terms.push((
+            -(pubkey.s_delta * x + pubkey.delta_after * y),
+            G2Prepared::from(r),
+        ));
+
+        current_delta = pubkey.delta_after;
+        let mut response = [0u8; 64];
+        response.copy_from_slice(Blake2b512::digest(&bytes).as_ref());
+        result.push(response);
+    }
+
+    if current_delta != p.vk.delta_g1 {
+        return Err(());
+    }
+
+    // e(g1, delta_g2) = e(current_delta, g2),
+    // e(sum(rho * h_before), g2) = e(sum(rho * h_after), delta_g2) and the same
+    // for L
+    let (h_before, h_after) = merge_pairs(&i.h, &p.h, &mut rng, threads, |done| {
+        progress(Stage::Query {
+            name: "H",
+            done,
+            total: i.h.len() as u64,
+        })
+    });
+    let (l_before, l_after) = merge_pairs(&i.l, &p.l, &mut rng, threads, |done| {
+        progress(Stage::Query {
+            name: "L",
+            done,
+            total: i.l.len() as u64,
+        })
+    });
+    let z = random_scalar(&mut rng);
+    terms.push((
+        h_before + l_before - current_delta * z,
+        G2Prepared::from(G2Affine::generator()),
+    ));
+    terms.push((
+        G1Affine::generator() * z - h_after - l_after,
+        G2Prepared::from(p.vk.delta_g2),
+    ));
+
+    let terms: Vec<(G1Affine, G2Prepared)> = terms
+        .into_iter()
+        .map(|(a, b)| (G1Affine::from(a), b))
+        .collect();
+    let terms: Vec<(&G1Affine, &G2Prepared)> = terms.iter().map(|(a, b)| (a, b)).collect();
+    progress(Stage::Pairing);
+    if multi_miller_loop(&terms).final_exponentiation() != Gt::identity() {
+        return Err(());
+    }
+
+    Ok(result)
+}
+
+/// Points of a contribution's public key.
+struct PublicKey {
+    delta_after: G1Affine,
+    s: G1Affine,
+    s_delta: G1Affine,
+    r_delta: G2Affine,
+    transcript: [u8; 64],
+}
+
+impl PublicKey {
+    fn parse(bytes: &[u8]) -> Option<PublicKey> {
+        let g1 = |i: usize| {
+            let bytes = bytes[i * G1_LEN..(i + 1) * G1_LEN].try_into().unwrap();
+            Option::from(G1Affine::from_uncompressed(bytes))
+        };
+        let g2_bytes = bytes[3 * G1_LEN..3 * G1_LEN + G2_LEN].try_into().unwrap();
+
+        Some(PublicKey {
+            delta_after: g1(0)?,
+            s: g1(1)?,
+            s_delta: g1(2)?,
+            r_delta: Option::from(G2Affine::from_uncompressed(g2_bytes))?,
+            transcript: bytes[3 * G1_LEN + G2_LEN..].try_into().unwrap(),
+        })
+    }
+}
+
+/// Same as phase2's `hash_to_g2`.
+fn hash_to_g2(digest: &[u8]) -> G2Projective {
+    G2Projective::random(&mut ChaChaRng::from_seed(digest[..32].try_into().unwrap()))
+}
+
+fn random_scalar<R: RngCore>(rng: &mut R) -> Scalar {
+    let mut bytes = [0u8; 64];
+    rng.fill_bytes(&mut bytes);
+    Scalar::from_bytes_wide(&bytes)
+}
+
+/// Returns `sum(rho_i * v1[i])` and `sum(rho_i * v2[i])` for random `rho_i`,
+/// split over `threads` threads. `progress` is called with the number of
+/// pairs done so far, first with 0 and last with all of them.
+fn merge_pairs<F: FnMut(u64)>(
+    v1: &[G1Affine],
+    v2: &[G1Affine],
+    rng: &mut ChaChaRng,
+    threads: usize,
+    mut progress: F,
+) -> (G1Projective, G1Projective) {
+    let threads = threads.max(1);
+    let chunk_size = v1.len().div_ceil(threads).max(1);
+    let done = &AtomicU64::new(0);
+    progress(0);
+
+    let merged = std::thread::scope(|scope| {
+        let chunks: Vec<_> = v1
+            .chunks(chunk_size)
+            .zip(v2.chunks(chunk_size))
+            .map(|(v1, v2)| {
+                let mut rng = ChaChaRng::from_rng(&mut *rng).expect("couldn't seed RNG");
+                scope.spawn(move || {
+                    let mut s = G1Projective::identity();
+                    let mut sx = G1Projective::identity();
+                    for (i, (a, b)) in v1.iter().zip(v2.iter()).enumerate() {
+                        let rho = random_scalar(&mut rng);
+                        s += a * rho;
+                        sx += b * rho;
+                        if i % 1024 == 1023 {
+                            done.fetch_add(1024, Ordering::Relaxed);
+                        }
+                    }
+                    (s, sx)
+                })
+            })
+            .collect();
+
+        while !chunks.iter().all(|chunk| chunk.is_finished()) {
+            std::thread::sleep(Duration::from_millis(100));
+            progress(done.load(Ordering::Relaxed));
+        }
+
+        chunks.into_iter().fold(
+            (G1Projective::identity(), G1Projective::identity()),
+            |(s, sx), chunk| {
+                let (a, b) = chunk.join().expect("merge thread panicked");
+                (s + a, sx + b)
+            },
+        )
+    });
+
+    progress(v1.len() as u64);
+    merged
+}
+
+/// Returns the hash of the constraint system recorded in `params`, which
+/// `MPCParameters` doesn't expose. It is serialized right after the Groth16
+/// parameters.
+fn cs_hash(params: &MPCParameters) -> [u8; 64] {
+    let mut window = Window {
+        start: groth16_len(params.get_params()),
+        pos: 0,
+        bytes: [0u8; 64],
+    };
+    params
+        .write(&mut window)
+        .expect("writing to a Window can't fail");
+    window.bytes
+}
+
+/// Writer keeping only the 64 bytes at offset `start`.
+struct Window {
+    start: u64,
+    pos: u64,
+    bytes: [u8; 64],
+}
+
+impl Write for Window {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Part of buf that falls into the window
+        let len = buf.len() as u64;
+        let from = self.start.saturating_sub(self.pos).min(len) as usize;
+        let to = (self.start + 64).saturating_sub(self.pos).min(len) as usize;
+        if from < to {
+            let at = (self.pos + from as u64 - self.start) as usize;
+            self.bytes[at..at + to - from].copy_from_slice(&buf[from..to]);
+        }
+        self.pos += len;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{Layout, DELTA_G2};
+    use crate::TestCircuit;
+    use std::io::Cursor;
+
+    #[test]
+    fn batch_matches_verify() {
+        let circuit = || TestCircuit { x: None };
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+        let mut params = MPCParameters::new(circuit()).unwrap();
+        assert_eq!(verify(&params, circuit()), params.verify(circuit()));
+
+        params.contribute(&mut rng, &0);
+        params.contribute(&mut rng, &0);
+        let expected = params.verify(circuit()).unwrap();
+        assert_eq!(verify(&params, circuit()), Ok(expected));
+
+        let mut bytes = vec![];
+        params.write(&mut bytes).unwrap();
+        let layout = Layout::scan(Cursor::new(&bytes)).unwrap();
+        let tamper = |offset: u64, point: &[u8]| {
+            let mut bytes = bytes.clone();
+            let offset = offset as usize;
+            bytes[offset..offset + point.len()].copy_from_slice(point);
+            MPCParameters::read(&bytes[..], false).unwrap()
+        };
+
+        let g1 = G1Affine::generator().to_uncompressed();
+        let g2 = G2Affine::generator().to_uncompressed();
+        for tampered in &[tamper(layout.h.offset, &g1), tamper(DELTA_G2, &g2)] {
+            assert!(tampered.verify(circuit()).is_err());
+            assert!(verify(tampered, circuit()).is_err());
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Rebuilds the params file a contributor wrote from the file they
+//! contributed to and the delta they uploaded with `contribute --delta`, and
+//! checks the contribution like `verify_transform` does.
+
+use masp_mpc::ceremony::{combined_hash, Ceremony, PointChecks, UnknownCircuits};
+use masp_mpc::circuits;
+use masp_mpc::delta::Delta;
+use masp_mpc::fs::{file_digest, AtomicFile};
+use masp_mpc::into_hex;
+use masp_mpc::progress::{self, Mode};
+use masp_phase2::verify_contribution;
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let checks = PointChecks::from_args(&mut args);
+    let unknown = UnknownCircuits::from_args(&mut args);
+    if args.len() != 4 {
+        println!(
+            "Usage: \n<in_params.params> <in.delta> <out_params.params> [--skip-point-checks] [--allow-unknown-circuits] [--quiet | --json]"
+        );
+        std::process::exit(exitcode::USAGE);
+    }
+    let in_params_filename = &args[1];
+    let delta_filename = &args[2];
+    let out_params_filename = &args[3];
+
+    let base = Ceremony::open(mode, in_params_filename, checks, unknown)
+        .expect("couldn't deserialize params");
+    let mut reader = progress::open(mode, delta_filename).expect("couldn't open delta");
+    let delta = Delta::read(&mut reader, &base).expect("couldn't deserialize delta");
+    reader.into_inner().finish();
+
+    if delta.base != file_digest(in_params_filename).expect("couldn't hash params") {
+        println!(
+            "{} was not contributed to {}",
+            delta_filename, in_params_filename
+        );
+        std::process::exit(exitcode::DATAERR);
+    }
+
+    // The new points come from the contributor, so they are always checked
+    mode.message(delta_filename, "checking every point");
+    let ceremony = delta.apply(&base, unknown).expect("couldn't apply delta");
+
+    let mut hashes = vec![];
+    for (before, after) in base.circuits.iter().zip(&ceremony.circuits) {
+        match verify_contribution(&before.params, &after.params) {
+            Ok(hash) => hashes.push(hash),
+            Err(_) => {
+                println!("invalid {} transformation!", circuits::label(&before.name));
+                std::process::exit(exitcode::DATAERR);
+            }
+        }
+    }
+    println!("Contribution hash: 0x{}", into_hex(&combined_hash(&hashes)));
+
+    let mut f = AtomicFile::create(out_params_filename, true).unwrap();
+    let total = ceremony.serialized_len();
+    let mut writer = progress::writer(mode, out_params_filename, total, &mut f);
+    ceremony
+        .write(&mut writer)
+        .expect("failed to write updated parameters");
+    writer.finish();
+    let summary = f.commit().expect("failed to write updated parameters");
+
+    println!("File digest: {}", into_hex(&summary.hash));
+}
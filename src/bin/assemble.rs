@@ -0,0 +1,70 @@
+//! Joins the chunks of a params file, as downloaded by `fetch`, checking each
+//! of them against the manifest.
+
+use masp_mpc::chunks::{self, Manifest};
+use masp_mpc::into_hex;
+use masp_mpc::progress::Mode;
+use std::fs::{self, File};
+use std::path::Path;
+
+fn usage() -> ! {
+    println!(
+        "Usage: \n<manifest> <out_params.params> [--chunk-dir <dir>] [--force] [--quiet | --json]"
+    );
+    std::process::exit(exitcode::USAGE);
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let mut paths = vec![];
+    let mut chunk_dir = None;
+    let mut force = false;
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--chunk-dir" => chunk_dir = Some(Path::new(rest.next().unwrap_or_else(|| usage()))),
+            "--force" => force = true,
+            _ => paths.push(arg),
+        }
+    }
+    if paths.len() != 2 {
+        usage();
+    }
+    let manifest_path = Path::new(paths[0]);
+    let out_params_filename = paths[1];
+    // The chunks are next to the manifest by default
+    let chunk_dir = chunk_dir.unwrap_or_else(|| manifest_path.parent().unwrap_or(Path::new(".")));
+
+    let f = File::open(manifest_path).expect("couldn't open manifest");
+    let manifest = Manifest::read(f).expect("couldn't read manifest");
+
+    // Only the sizes, the contents are checked while assembling
+    let missing: Vec<usize> = (0..manifest.chunks.len())
+        .filter(|&i| {
+            let path = chunk_dir.join(manifest.chunk_name(i));
+            fs::metadata(path).map_or(true, |m| m.len() != manifest.chunk_len(i))
+        })
+        .collect();
+    if !missing.is_empty() {
+        println!(
+            "{} of {} chunks are missing or incomplete, run fetch again:",
+            missing.len(),
+            manifest.chunks.len()
+        );
+        for i in missing {
+            println!("  {}", manifest.chunk_name(i));
+        }
+        std::process::exit(exitcode::DATAERR);
+    }
+
+    let summary = chunks::assemble(mode, &manifest, chunk_dir, out_params_filename, force)
+        .unwrap_or_else(|e| {
+            println!("couldn't assemble {}: {}", out_params_filename, e);
+            std::process::exit(exitcode::DATAERR);
+        });
+
+    println!("Wrote {}", out_params_filename);
+    println!("File digest: {}", into_hex(&summary.hash));
+}
@@ -1,33 +1,38 @@
-use blake2::Blake2b512;
 use itertools::Itertools;
-use masp_phase2::MPCParameters;
-use sha2::{Digest, Sha256};
-use std::fs::File;
-use std::fs::OpenOptions;
-use std::io::BufReader;
-use std::io::Write;
+use masp_mpc::batch;
+use masp_mpc::ceremony::{combined_hash, Ceremony, PointChecks, UnknownCircuits};
+use masp_mpc::circuits;
+use masp_mpc::fs::{file_digest, AtomicFile};
+use masp_mpc::into_hex;
+use masp_mpc::progress::{self, Mode, Progress, Unit};
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let checks = PointChecks::from_args(&mut args);
+    let unknown = UnknownCircuits::from_args(&mut args);
     if args.len() != 3 {
-        println!("Usage: \n<in_params.params> <out_params.params>");
+        println!(
+            "Usage: \n<in_params.params> <out_params.params> [--skip-point-checks] [--allow-unknown-circuits] [--quiet | --json]"
+        );
         std::process::exit(exitcode::USAGE);
     }
     let in_params_filename = &args[1];
     let out_params_filename = &args[2];
     let print_progress = true;
 
-    let disallow_points_at_infinity = false;
+    //let disallow_points_at_infinity = false;
 
     // Create an RNG based on the outcome of the random beacon
     let mut rng = {
-        use byteorder::{BigEndian, ReadBytesExt};
         use rand::SeedableRng;
         use rand_chacha::ChaChaRng;
         use std::convert::TryInto;
-        
+
         // The hash after 2^42 iterations of SHA256 on the randomness beacon
-        let mut cur_hash = hex::decode("8ecb1e82f5e01c8c0353e35bf0a782f21528f14154faa57436a89f02dd260224").unwrap();
+        let cur_hash =
+            hex::decode("8ecb1e82f5e01c8c0353e35bf0a782f21528f14154faa57436a89f02dd260224")
+                .unwrap();
 
         if cur_hash.len() != 32 {
             println!("Beacon hash should be 32 bytes long");
@@ -45,76 +50,59 @@ fn main() {
 
     println!("Done creating a beacon RNG");
 
-    let reader = OpenOptions::new()
-        .read(true)
-        .open(in_params_filename)
-        .expect("unable to open.");
-    let mut reader = BufReader::with_capacity(1024 * 1024, reader);
-
-    reader.seek_relative(64).unwrap();
-    let mut spend_params = MPCParameters::read(&mut reader, false).expect("unable to read params");
-
-    println!("Contributing to MASP Spend {}...", in_params_filename);
-
-    let spend_hash = spend_params.contribute(&mut rng, &0);
-
-    let mut output_params = MPCParameters::read(&mut reader, false).expect("unable to read params");
-
-    println!("Contributing to MASP Output {}...", in_params_filename);
-
-    let output_hash = output_params.contribute(&mut rng, &0);
-
-    let mut convert_params =
-        MPCParameters::read(&mut reader, false).expect("unable to read MASP Convert params");
+    let mut ceremony =
+        Ceremony::open(mode, in_params_filename, checks, unknown).expect("unable to read params");
+
+    // Record which file this round was contributed to
+    let parent = file_digest(in_params_filename).expect("unable to hash params");
+    ceremony.push_history(parent);
+
+    let points = ceremony
+        .circuits
+        .iter()
+        .map(|c| c.contribution_points())
+        .sum();
+    // Contributions report no progress, so estimate it from this machine
+    let rate = batch::contribution_rate(batch::default_threads());
+    let mut progress = Progress::new(mode, "contributing", Unit::Points, points);
+    mode.message(
+        "contributing",
+        "progress is estimated from this machine's speed",
+    );
 
-    println!("Contributing to MASP Convert {}...", in_params_filename);
-    let mut progress_update_interval: u32 = 0;
-    /*if print_progress {
-        let parsed = args[5].parse::<u32>();
-        if !parsed.is_err() {
-            progress_update_interval = parsed.unwrap();
-        }
+    let mut hashes = vec![];
+    for circuit in &mut ceremony.circuits {
+        println!(
+            "Contributing to {} {}...",
+            circuits::label(&circuit.name),
+            in_params_filename
+        );
+        let points = circuit.contribution_points();
+        let params = &mut circuit.params;
+        let rng = &mut rng;
+        hashes.push(progress::estimated(&mut progress, points, rate, || {
+            params.contribute(rng, &0)
+        }));
     }
-    */
-    let convert_hash = convert_params.contribute(&mut rng, &progress_update_interval);
+    progress.finish();
 
-    let mut h = Blake2b512::new();
-    h.update(&spend_hash);
-    h.update(&output_hash);
-    h.update(&convert_hash);
-    let h = h.finalize();
+    let h = combined_hash(&hashes);
 
     println!("Contribution hash: 0x{:02x}", h.iter().format(""));
 
-    let mut f = File::create(out_params_filename).unwrap();
-    for _ in 0..64 {
-        f.write_all(&[0u8]).unwrap();
-    }
-    println!("Writing MASP Spend parameters to {}.", out_params_filename);
-    spend_params
-        .write(&mut f)
-        .expect("failed to write updated MASP Spend parameters");
-    if print_progress {
-        println!("wrote MASP Spend");
-    }
-
-    println!("Writing MASP Output parameters to {}.", out_params_filename);
-    output_params
-        .write(&mut f)
-        .expect("failed to write updated MASP Output parameters");
-    if print_progress {
-        println!("wrote MASP Output");
-    }
-
-    println!(
-        "Writing MASP Convert parameters to {}.",
-        out_params_filename
-    );
-    convert_params
-        .write(&mut f)
-        .expect("failed to write updated MASP Convert parameters");
+    println!("Writing parameters to {}.", out_params_filename);
+    let mut f = AtomicFile::create(out_params_filename, true).unwrap();
+    let total = ceremony.serialized_len();
+    let mut writer = progress::writer(mode, out_params_filename, total, &mut f);
+    ceremony
+        .write(&mut writer)
+        .expect("failed to write updated parameters");
+    writer.finish();
+    let summary = f.commit().expect("failed to write updated parameters");
+
+    println!("File digest: {}", into_hex(&summary.hash));
     if print_progress {
-        println!("wrote MASP Convert");
+        println!("wrote parameters");
     }
 }
 
@@ -0,0 +1,57 @@
+//! Splits a params file into chunks listed in a manifest, to be downloaded
+//! with `fetch` and joined back with `assemble`.
+
+use masp_mpc::chunks::{self, Manifest, DEFAULT_CHUNK_SIZE, MAX_CHUNK_SIZE};
+use masp_mpc::into_hex;
+use masp_mpc::progress::Mode;
+use std::path::PathBuf;
+
+fn usage() -> ! {
+    println!(
+        "Usage: \n<params.params> [--out-dir <dir>] [--chunk-size <bytes>] [--force] [--quiet | --json]"
+    );
+    std::process::exit(exitcode::USAGE);
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let mut params_filename = None;
+    let mut out_dir = PathBuf::from(".");
+    let mut chunk_size = DEFAULT_CHUNK_SIZE;
+    let mut force = false;
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--out-dir" => out_dir = rest.next().unwrap_or_else(|| usage()).into(),
+            "--chunk-size" => {
+                chunk_size = match rest.next().map(|size| size.parse()) {
+                    Some(Ok(size)) if size > 0 && size <= MAX_CHUNK_SIZE => size,
+                    _ => usage(),
+                }
+            }
+            "--force" => force = true,
+            _ if params_filename.is_none() => params_filename = Some(arg),
+            _ => usage(),
+        }
+    }
+    let params_filename = params_filename.unwrap_or_else(|| usage());
+
+    let manifest = chunks::split(mode, params_filename, &out_dir, chunk_size, force)
+        .unwrap_or_else(|e| {
+            println!("couldn't split {}: {}", params_filename, e);
+            std::process::exit(exitcode::CANTCREAT);
+        });
+
+    println!(
+        "Wrote {} chunks to {}",
+        manifest.chunks.len(),
+        out_dir.display()
+    );
+    println!(
+        "Wrote manifest to {}",
+        out_dir.join(Manifest::file_name(&manifest.name)).display()
+    );
+    println!("File digest: {}", into_hex(&manifest.digest));
+}
@@ -0,0 +1,77 @@
+//! Prints the constraint system of each circuit linked into this binary and
+//! compares it against the fingerprints recorded in a ceremony file.
+
+use masp_mpc::ceremony::Header;
+use masp_mpc::circuits::{self, Fingerprint, MaspCircuit};
+use masp_mpc::into_hex;
+use masp_mpc::progress::Mode;
+use std::fs::File;
+use std::io::BufReader;
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    if args.len() > 2 {
+        println!("Usage: \n[params.params] [--quiet | --json]");
+        std::process::exit(exitcode::USAGE);
+    }
+
+    let header = args.get(1).map(|params_filename| {
+        let f = File::open(params_filename).expect("couldn't open params");
+        Header::read(BufReader::new(f)).expect("couldn't read params header")
+    });
+
+    let names: Vec<String> = match &header {
+        Some(header) => header.circuits.iter().map(|c| c.name.clone()).collect(),
+        None => circuits::MASP_CIRCUITS
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    };
+
+    let mut mismatches = 0;
+    for (i, name) in names.iter().enumerate() {
+        let label = circuits::label(name);
+        println!("{}", label);
+
+        mode.message(&label, "synthesizing circuit");
+        let fingerprint = match MaspCircuit::fingerprint(name) {
+            Some(fingerprint) => fingerprint,
+            None => {
+                println!("  not linked into this binary");
+                mismatches += 1;
+                continue;
+            }
+        };
+        print_fingerprint(&fingerprint);
+
+        if let Some(header) = &header {
+            match &header.circuits[i].fingerprint {
+                Some(expected) if *expected == fingerprint => {
+                    println!("  matches ceremony file");
+                }
+                Some(expected) => {
+                    println!("  MISMATCH, ceremony file expects:");
+                    print_fingerprint(expected);
+                    mismatches += 1;
+                }
+                None => println!("  ceremony file records no fingerprint"),
+            }
+        }
+    }
+
+    if mismatches > 0 {
+        println!(
+            "{} circuit(s) differ from the ceremony file; do not contribute with this binary",
+            mismatches
+        );
+        std::process::exit(exitcode::DATAERR);
+    }
+}
+
+fn print_fingerprint(fingerprint: &Fingerprint) {
+    println!("  constraints: {}", fingerprint.constraints);
+    println!("  inputs:      {}", fingerprint.inputs);
+    println!("  auxiliaries: {}", fingerprint.aux);
+    println!("  hash:        {}", into_hex(&fingerprint.hash));
+}
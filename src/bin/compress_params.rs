@@ -0,0 +1,41 @@
+//! Rewrites a ceremony file with the H, L, A and B queries stored as
+//! compressed points, roughly halving its size.
+
+use masp_mpc::ceremony::{Ceremony, PointChecks, UnknownCircuits};
+use masp_mpc::fs::AtomicFile;
+use masp_mpc::into_hex;
+use masp_mpc::layout::Encoding;
+use masp_mpc::progress::{self, Mode};
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let checks = PointChecks::from_args(&mut args);
+    let unknown = UnknownCircuits::from_args(&mut args);
+    if args.len() != 3 {
+        println!(
+            "Usage: \n<in_params.params> <out_params.params> [--skip-point-checks] [--allow-unknown-circuits] [--quiet | --json]"
+        );
+        std::process::exit(exitcode::USAGE);
+    }
+    let in_params_filename = &args[1];
+    let out_params_filename = &args[2];
+
+    let mut ceremony = Ceremony::open(mode, in_params_filename, checks, unknown)
+        .expect("couldn't deserialize params");
+    if ceremony.encoding == Encoding::Compressed {
+        println!("{} is already compressed", in_params_filename);
+    }
+    ceremony.encoding = Encoding::Compressed;
+
+    let mut f = AtomicFile::create(out_params_filename, true).unwrap();
+    let total = ceremony.serialized_len();
+    let mut writer = progress::writer(mode, out_params_filename, total, &mut f);
+    ceremony
+        .write(&mut writer)
+        .expect("failed to write compressed parameters");
+    writer.finish();
+    let summary = f.commit().expect("failed to write compressed parameters");
+
+    println!("File digest: {}", into_hex(&summary.hash));
+}
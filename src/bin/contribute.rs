@@ -1,17 +1,29 @@
 use blake2::{Blake2b512, Digest};
 use itertools::Itertools;
-use masp_phase2::MPCParameters;
-use std::fs::File;
-use std::fs::OpenOptions;
+use masp_mpc::batch;
+use masp_mpc::ceremony::{combined_hash, Ceremony, PointChecks, UnknownCircuits};
+use masp_mpc::circuits;
+use masp_mpc::delta::Delta;
+use masp_mpc::fs::{file_digest, AtomicFile};
+use masp_mpc::into_hex;
+use masp_mpc::progress::{self, Mode, Progress, Unit};
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let checks = PointChecks::from_args(&mut args);
+    let unknown = UnknownCircuits::from_args(&mut args);
+    // Only write what the contribution changed, for apply_delta
+    let delta_only = args.iter().any(|arg| arg == "--delta");
+    args.retain(|arg| arg != "--delta");
     if args.len() != 4 && args.len() != 6 {
-        println!("Usage: \n<in_params.params> <out_params.params> <in_str_entropy>");
+        println!(
+            "Usage: \n<in_params.params> <out_params.params> <in_str_entropy> [--delta] [--skip-point-checks] [--allow-unknown-circuits] [--quiet | --json]"
+        );
         std::process::exit(exitcode::USAGE);
     }
     if args.len() == 6 && args[4] != "-v" {
-        println!("Usage: \n<in_params.params> <out_params.params> <in_str_entropy> -v <progress_interval>");
+        println!("Usage: \n<in_params.params> <out_params.params> <in_str_entropy> -v <progress_interval> [--delta] [--skip-point-checks] [--allow-unknown-circuits] [--quiet | --json]");
         std::process::exit(exitcode::USAGE);
     }
     let in_params_filename = &args[1];
@@ -37,95 +49,89 @@ fn main() {
             // Gather 1024 bytes of entropy from the system
             for _ in 0..1024 {
                 let r: u8 = system_rng.gen();
-                h.update(&[r]);
+                h.update([r]);
             }
 
             // Hash it all up to make a seed
-            h.update(&entropy.as_bytes());
+            h.update(entropy.as_bytes());
             h.finalize()
         };
 
         ChaChaRng::from_seed(h[0..32].try_into().unwrap())
     };
 
-    let reader = OpenOptions::new()
-        .read(true)
-        .open(in_params_filename)
-        .expect("unable to open.");
+    let mut ceremony =
+        Ceremony::open(mode, in_params_filename, checks, unknown).expect("unable to read params");
 
-    let mut spend_params =
-        MPCParameters::read(&reader, false).expect("unable to read MASP Spend params");
+    // Record which file this round was contributed to
+    let parent = file_digest(in_params_filename).expect("unable to hash params");
+    ceremony.push_history(parent);
 
-    println!("Contributing to MASP Spend {}...", in_params_filename);
     let mut progress_update_interval: u32 = 0;
     if print_progress {
-        let parsed = args[5].parse::<u32>();
-        if !parsed.is_err() {
-            progress_update_interval = parsed.unwrap();
+        if let Ok(parsed) = args[5].parse::<u32>() {
+            progress_update_interval = parsed;
         }
     }
-    let spend_hash = spend_params.contribute(&mut rng, &progress_update_interval);
 
-    let mut output_params =
-        MPCParameters::read(&reader, false).expect("unable to read MASP Output params");
-
-    println!("Contributing to MASP Output {}...", in_params_filename);
-    let mut progress_update_interval: u32 = 0;
-    if print_progress {
-        let parsed = args[5].parse::<u32>();
-        if !parsed.is_err() {
-            progress_update_interval = parsed.unwrap();
-        }
-    }
-    let output_hash = output_params.contribute(&mut rng, &progress_update_interval);
-
-    let mut convert_params =
-        MPCParameters::read(&reader, false).expect("unable to read MASP Convert params");
+    let points = ceremony
+        .circuits
+        .iter()
+        .map(|c| c.contribution_points())
+        .sum();
+    // Contributions report no progress, so estimate it from this machine
+    let rate = batch::contribution_rate(batch::default_threads());
+    let mut progress = Progress::new(mode, "contributing", Unit::Points, points);
+    mode.message(
+        "contributing",
+        "progress is estimated from this machine's speed",
+    );
 
-    println!("Contributing to MASP Convert {}...", in_params_filename);
-    let mut progress_update_interval: u32 = 0;
-    if print_progress {
-        let parsed = args[5].parse::<u32>();
-        if !parsed.is_err() {
-            progress_update_interval = parsed.unwrap();
-        }
+    let mut hashes = vec![];
+    for circuit in &mut ceremony.circuits {
+        println!(
+            "Contributing to {} {}...",
+            circuits::label(&circuit.name),
+            in_params_filename
+        );
+        let points = circuit.contribution_points();
+        let params = &mut circuit.params;
+        let rng = &mut rng;
+        hashes.push(progress::estimated(&mut progress, points, rate, || {
+            params.contribute(rng, &progress_update_interval)
+        }));
     }
-    let convert_hash = convert_params.contribute(&mut rng, &progress_update_interval);
+    progress.finish();
 
-    let mut h = Blake2b512::new();
-    h.update(&spend_hash);
-    h.update(&output_hash);
-    h.update(&convert_hash);
-    let h = h.finalize();
+    let h = combined_hash(&hashes);
 
     println!("Contribution hash: 0x{:02x}", h.iter().format(""));
 
-    let mut f = File::create(out_params_filename).unwrap();
-
-    println!("Writing MASP Spend parameters to {}.", out_params_filename);
-    spend_params
-        .write(&mut f)
-        .expect("failed to write updated MASP Spend parameters");
-    if print_progress {
-        println!("wrote MASP Spend");
+    let delta = if delta_only {
+        println!("Writing contribution delta to {}.", out_params_filename);
+        Some(Delta::from_ceremony(&ceremony).expect("couldn't extract contribution delta"))
+    } else {
+        println!("Writing parameters to {}.", out_params_filename);
+        None
+    };
+    let mut f = AtomicFile::create(out_params_filename, true).unwrap();
+    let total = delta
+        .as_ref()
+        .map_or_else(|| ceremony.serialized_len(), Delta::serialized_len);
+    let mut writer = progress::writer(mode, out_params_filename, total, &mut f);
+    match &delta {
+        Some(delta) => delta.write(&mut writer),
+        None => ceremony.write(&mut writer),
     }
+    .expect("failed to write updated parameters");
+    writer.finish();
+    let summary = f.commit().expect("failed to write updated parameters");
 
-    println!("Writing MASP Output parameters to {}.", out_params_filename);
-    output_params
-        .write(&mut f)
-        .expect("failed to write updated MASP Output parameters");
-    if print_progress {
-        println!("wrote MASP Output");
+    match delta {
+        Some(_) => println!("Delta digest: {}", into_hex(&summary.hash)),
+        None => println!("File digest: {}", into_hex(&summary.hash)),
     }
-
-    println!(
-        "Writing MASP Convert parameters to {}.",
-        out_params_filename
-    );
-    convert_params
-        .write(&mut f)
-        .expect("failed to write updated MASP Convert parameters");
     if print_progress {
-        println!("wrote MASP Convert");
+        println!("wrote parameters");
     }
 }
@@ -0,0 +1,124 @@
+//! Extracts the final Groth16 parameters of each circuit in the format
+//! `masp_proofs` loads them, dropping the MPC transcript.
+
+use masp_mpc::ceremony::{PointChecks, UnknownCircuits};
+use masp_mpc::circuits;
+use masp_mpc::fs::AtomicFile;
+use masp_mpc::into_hex;
+use masp_mpc::layout;
+use masp_mpc::mmap::MappedCeremony;
+use masp_mpc::progress::{self, Mode};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+fn usage() -> ! {
+    println!("Usage: \n<params.params> [out_dir] [--force] [--skip-point-checks] [--allow-unknown-circuits] [--quiet | --json]");
+    std::process::exit(exitcode::USAGE);
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let checks = PointChecks::from_args(&mut args);
+    let unknown = UnknownCircuits::from_args(&mut args);
+    let force = args.iter().any(|arg| arg == "--force");
+    args.retain(|arg| arg != "--force");
+    if args.len() != 2 && args.len() != 3 {
+        usage();
+    }
+    let out_dir = Path::new(args.get(2).map_or(".", |dir| dir.as_str()));
+
+    // Only the circuit being exported is paged in
+    let ceremony = MappedCeremony::open(&args[1]).expect("couldn't open params");
+    checks.report(mode, &args[1]);
+
+    // Not `masp-<circuit>.params`, which split_params writes the full
+    // `MPCParameters` to
+    let paths: Vec<(PathBuf, PathBuf)> = ceremony
+        .circuits()
+        .iter()
+        .map(|circuit| {
+            (
+                out_dir.join(format!("masp-{}.groth16.params", circuit.name())),
+                out_dir.join(format!("masp-{}.vk", circuit.name())),
+            )
+        })
+        .collect();
+
+    // Refuse before writing anything, rather than leaving a partial set behind
+    if !force {
+        for path in paths.iter().flat_map(|(params, vk)| [params, vk]) {
+            if path.exists() {
+                println!(
+                    "`{}` already exists, pass --force to overwrite",
+                    path.display()
+                );
+                std::process::exit(exitcode::CANTCREAT);
+            }
+        }
+    }
+
+    // Nothing is moved into place until every circuit has been read
+    let mut files = vec![];
+    for (circuit, (params_path, vk_path)) in ceremony.circuits().iter().zip(paths) {
+        let label = circuits::label(circuit.name());
+
+        // Unchecked uncompressed parameters are copied as they are, once
+        // their circuit is known to be the registered one
+        let params = match (checks, circuit.groth16_bytes()) {
+            (PointChecks::Skip, Some(_)) => {
+                circuit
+                    .check_limits(unknown)
+                    .unwrap_or_else(|e| panic!("couldn't read {} params: {}", label, e));
+                None
+            }
+            _ => Some(
+                circuit
+                    .read_params(checks.checked(), unknown)
+                    .unwrap_or_else(|e| panic!("couldn't deserialize {} params: {}", label, e)),
+            ),
+        };
+
+        let mut params_file = create(&params_path, force);
+        match &params {
+            Some(params) => {
+                let groth16 = params.get_params();
+                let total = layout::groth16_len(groth16);
+                let mut writer = progress::writer(mode, &params_path, total, &mut params_file);
+                groth16.write(&mut writer).map(|()| {
+                    writer.finish();
+                })
+            }
+            None => params_file.write_all(circuit.groth16_bytes().unwrap()),
+        }
+        .unwrap_or_else(|e| panic!("couldn't write {} parameters: {}", label, e));
+
+        let mut vk_file = create(&vk_path, force);
+        vk_file
+            .write_all(circuit.vk_bytes())
+            .unwrap_or_else(|e| panic!("couldn't write {} verifying key: {}", label, e));
+
+        files.push((label, params_file, vk_file));
+    }
+
+    // The digests are BLAKE2b-512, as pinned by `masp_proofs`
+    for (label, params_file, vk_file) in files {
+        let params = params_file
+            .commit()
+            .unwrap_or_else(|e| panic!("couldn't write {} parameters: {}", label, e));
+        let vk = vk_file
+            .commit()
+            .unwrap_or_else(|e| panic!("couldn't write {} verifying key: {}", label, e));
+
+        println!("{}", label);
+        println!("  {} {}", params.path.display(), into_hex(&params.hash));
+        println!("  {} {}", vk.path.display(), into_hex(&vk.hash));
+    }
+}
+
+fn create(path: &Path, force: bool) -> AtomicFile {
+    AtomicFile::create(path, force).unwrap_or_else(|e| {
+        println!("couldn't create `{}`: {}", path.display(), e);
+        std::process::exit(exitcode::CANTCREAT);
+    })
+}
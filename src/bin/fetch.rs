@@ -0,0 +1,174 @@
+//! Downloads the chunks of a params file over HTTP, checking each against
+//! the manifest. Chunks already downloaded are kept, and an interrupted chunk
+//! is resumed where it stopped, so running it again after a failure only
+//! fetches what is missing.
+
+use masp_mpc::chunks::Manifest;
+use masp_mpc::fs::AtomicFile;
+use masp_mpc::progress::{Mode, Progress, Unit};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Upper bound on the size of a manifest.
+const MAX_MANIFEST_LEN: u64 = 16 << 20;
+
+fn usage() -> ! {
+    println!("Usage: \n<manifest_url> [--out-dir <dir>] [--retries <n>] [--quiet | --json]");
+    std::process::exit(exitcode::USAGE);
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let mut manifest_url = None;
+    let mut out_dir = PathBuf::from(".");
+    let mut retries = 3;
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--out-dir" => out_dir = rest.next().unwrap_or_else(|| usage()).into(),
+            "--retries" => {
+                retries = match rest.next().map(|n| n.parse()) {
+                    Some(Ok(n)) => n,
+                    _ => usage(),
+                }
+            }
+            _ if manifest_url.is_none() => manifest_url = Some(arg),
+            _ => usage(),
+        }
+    }
+    let manifest_url = manifest_url.unwrap_or_else(|| usage());
+    // Chunks are next to the manifest
+    let base_url = &manifest_url[..manifest_url.rfind('/').map_or(0, |i| i + 1)];
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(30))
+        .timeout_read(Duration::from_secs(60))
+        .build();
+
+    let manifest = agent
+        .get(manifest_url)
+        .call()
+        .map_err(|e| e.to_string())
+        .and_then(|response| {
+            Manifest::read(response.into_reader().take(MAX_MANIFEST_LEN)).map_err(|e| e.to_string())
+        })
+        .unwrap_or_else(|e| {
+            println!("couldn't fetch manifest {}: {}", manifest_url, e);
+            std::process::exit(exitcode::UNAVAILABLE);
+        });
+    fs::create_dir_all(&out_dir).expect("couldn't create output directory");
+    let manifest_path = out_dir.join(Manifest::file_name(&manifest.name));
+    let mut f = AtomicFile::create(&manifest_path, true).expect("couldn't write manifest");
+    manifest.write(&mut f).expect("couldn't write manifest");
+    f.commit().expect("couldn't write manifest");
+
+    let missing = manifest
+        .missing_chunks(&out_dir)
+        .expect("couldn't read chunks");
+    let have: u64 = (0..manifest.chunks.len())
+        .filter(|i| !missing.contains(i))
+        .map(|i| manifest.chunk_len(i))
+        .sum();
+    println!(
+        "{}: {} of {} chunks to fetch",
+        manifest.name,
+        missing.len(),
+        manifest.chunks.len()
+    );
+
+    let task = format!("fetching {}", manifest.name);
+    let mut progress = Progress::new(mode, task, Unit::Bytes, manifest.len);
+    progress.set(have);
+
+    let mut failed = vec![];
+    for i in missing {
+        let name = manifest.chunk_name(i);
+        let url = format!("{}{}", base_url, name);
+        let path = out_dir.join(&name);
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match fetch_chunk(&agent, &url, &manifest, i, &path, &mut progress) {
+                Ok(()) => break,
+                Err(e) if attempt <= retries => {
+                    println!("couldn't fetch {}: {}, retrying", name, e);
+                    std::thread::sleep(Duration::from_secs(attempt));
+                }
+                Err(e) => {
+                    println!("couldn't fetch {}: {}", name, e);
+                    failed.push(name);
+                    break;
+                }
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        println!(
+            "{} chunks couldn't be fetched, run fetch again to resume",
+            failed.len()
+        );
+        std::process::exit(exitcode::UNAVAILABLE);
+    }
+    progress.finish();
+    println!(
+        "Fetched {}, run assemble {} <out_params.params>",
+        manifest.name,
+        manifest_path.display()
+    );
+}
+
+/// Downloads chunk `i` to `path`, through `<path>.part` so that a partial
+/// download can be resumed with a range request.
+fn fetch_chunk(
+    agent: &ureq::Agent,
+    url: &str,
+    manifest: &Manifest,
+    i: usize,
+    path: &Path,
+    progress: &mut Progress,
+) -> Result<(), String> {
+    let len = manifest.chunk_len(i);
+    let mut part_name = path.file_name().unwrap().to_os_string();
+    part_name.push(".part");
+    let part = path.with_file_name(part_name);
+
+    let have = fs::metadata(&part).map_or(0, |m| m.len());
+    let mut request = agent.get(url);
+    if have > 0 && have < len {
+        request = request.set("Range", &format!("bytes={}-", have));
+    }
+    let response = request.call().map_err(|e| e.to_string())?;
+
+    // Servers ignoring the range send the whole chunk
+    let (mut f, start) = if response.status() == 206 {
+        let f = OpenOptions::new().append(true).open(&part);
+        (f.map_err(|e| e.to_string())?, have)
+    } else {
+        (File::create(&part).map_err(|e| e.to_string())?, 0)
+    };
+
+    let mut reader = response.into_reader().take(len - start);
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let n = reader.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        f.write_all(&buf[..n]).map_err(|e| e.to_string())?;
+        progress.add(n as u64);
+    }
+    f.sync_all().map_err(|e| e.to_string())?;
+    drop(f);
+
+    if !manifest.check_chunk(i, &part).map_err(|e| e.to_string())? {
+        let _ = fs::remove_file(&part);
+        return Err("chunk doesn't match the manifest".into());
+    }
+    fs::rename(&part, path).map_err(|e| e.to_string())
+}
@@ -0,0 +1,110 @@
+//! Prints the structure of a ceremony file: its header, and where each
+//! section of every circuit's parameters lies. No point is deserialized, so
+//! this also works on files other commands reject.
+
+use masp_mpc::ceremony::MAGIC;
+use masp_mpc::circuits;
+use masp_mpc::into_hex;
+use masp_mpc::layout::{Encoding, Query, CS_HASH_LEN};
+use masp_mpc::mmap::{MappedCeremony, MappedParams};
+use masp_mpc::progress::Mode;
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    if args.len() != 2 {
+        println!("Usage: \n<params.params> [--quiet | --json]");
+        std::process::exit(exitcode::USAGE);
+    }
+    let params_filename = &args[1];
+
+    let ceremony = MappedCeremony::open(params_filename).expect("couldn't open params");
+    let header = ceremony.header();
+    let bytes = ceremony.bytes();
+
+    println!("File:          {}", params_filename);
+    println!("Length:        {} bytes", bytes.len());
+    mode.message(params_filename, "hashing file");
+    println!(
+        "File digest:   {}",
+        into_hex(blake2b_simd::blake2b(bytes).as_bytes())
+    );
+    if bytes[..MAGIC.len()] == MAGIC {
+        let mut version = [0u8; 4];
+        version.copy_from_slice(&bytes[MAGIC.len()..MAGIC.len() + 4]);
+        println!("Version:       {}", u32::from_be_bytes(version));
+    } else {
+        println!("Version:       legacy, without header");
+    }
+    println!(
+        "Encoding:      {}",
+        match header.encoding {
+            Encoding::Uncompressed => "uncompressed",
+            Encoding::Compressed => "compressed",
+        }
+    );
+    println!(
+        "Header:        {} bytes",
+        ceremony.layout.circuits.first().map_or(0, |c| c.start)
+    );
+    println!("History:       {} rounds", header.history.len());
+    for (i, digest) in header.history.iter().enumerate() {
+        println!("  {:5}  {}", i, into_hex(digest));
+    }
+    println!("Circuits:      {}", header.circuits.len());
+
+    for circuit in ceremony.circuits() {
+        print_circuit(&circuit);
+    }
+}
+
+fn print_circuit(circuit: &MappedParams) {
+    let layout = circuit.layout;
+    println!();
+    println!("{}", circuits::label(circuit.name()));
+    println!("  name:        {}", circuit.name());
+    match &circuit.entry.fingerprint {
+        Some(fingerprint) => {
+            println!("  constraints: {}", fingerprint.constraints);
+            println!("  inputs:      {}", fingerprint.inputs);
+            println!("  auxiliaries: {}", fingerprint.aux);
+            println!("  hash:        {}", into_hex(&fingerprint.hash));
+        }
+        None => println!("  no fingerprint recorded"),
+    }
+    println!("  delta_g1:    {}", into_hex(circuit.delta_g1()));
+    println!("  delta_g2:    {}", into_hex(circuit.delta_g2()));
+    println!("  cs_hash:     {}", into_hex(circuit.cs_hash()));
+
+    // Offsets are absolute, and the queries' past their length prefix
+    println!("  section        offset         end       count");
+    print_section("params", layout.start, layout.end(), None);
+    print_query("IC", &layout.ic);
+    print_query("H", &layout.h);
+    print_query("L", &layout.l);
+    print_query("A", &layout.a);
+    print_query("B_G1", &layout.b_g1);
+    print_query("B_G2", &layout.b_g2);
+    print_section(
+        "cs_hash",
+        layout.cs_hash,
+        layout.cs_hash + CS_HASH_LEN as u64,
+        None,
+    );
+    print_query("pubkeys", &layout.contributions);
+
+    println!("  contributions: {}", layout.contributions.len);
+    for (i, hash) in circuit.contribution_hashes().iter().enumerate() {
+        println!("  {:5}  {}", i + 1, into_hex(hash));
+    }
+}
+
+fn print_query(name: &str, query: &Query) {
+    print_section(name, query.offset, query.end(), Some(query.len));
+}
+
+fn print_section(name: &str, offset: u64, end: u64, count: Option<usize>) {
+    let count = count.map_or(String::new(), |count| count.to_string());
+    let line = format!("  {:8} {:>12} {:>11} {:>11}", name, offset, end, count);
+    println!("{}", line.trim_end());
+}
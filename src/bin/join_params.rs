@@ -0,0 +1,160 @@
+//! This binary joins per-circuit parameter files, as written by
+//! `split_params`, back into a single ceremony file.
+
+use masp_mpc::ceremony::{
+    combined_hashes, delta_matches_contributions, Ceremony, CircuitEntry, CircuitParams,
+    PointChecks, UnknownCircuits,
+};
+use masp_mpc::circuits;
+use masp_mpc::fs::AtomicFile;
+use masp_mpc::into_hex;
+use masp_mpc::layout::{self, Encoding};
+use masp_mpc::progress::{self, Mode};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+fn usage() -> ! {
+    println!(
+        "Usage: \n<out_params.params> <circuit>=<in_params.params> ... [--expect <hashes.txt>] [--force] [--skip-point-checks] [--allow-unknown-circuits] [--quiet | --json]"
+    );
+    println!("or, for the MASP circuits:");
+    println!("<out_params.params> <spend.params> <output.params> <convert.params> [--expect <hashes.txt>] [--force] [--skip-point-checks] [--allow-unknown-circuits] [--quiet | --json]");
+    std::process::exit(exitcode::USAGE);
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let checks = PointChecks::from_args(&mut args);
+    let unknown = UnknownCircuits::from_args(&mut args);
+    let mut out_params_filename = None;
+    let mut inputs = vec![];
+    let mut expect = None;
+    let mut force = false;
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--expect" => expect = Some(rest.next().unwrap_or_else(|| usage())),
+            "--force" => force = true,
+            _ if out_params_filename.is_none() => out_params_filename = Some(arg),
+            _ => inputs.push(arg),
+        }
+    }
+    let out_params_filename = out_params_filename.unwrap_or_else(|| usage());
+
+    // Either every input is named, or they are the MASP circuits in order
+    let inputs: Vec<(String, String)> = if inputs.iter().all(|arg| arg.contains('=')) {
+        inputs
+            .iter()
+            .map(|arg| {
+                let (name, path) = arg.split_at(arg.find('=').unwrap());
+                (name.to_string(), path[1..].to_string())
+            })
+            .collect()
+    } else if inputs.len() == circuits::MASP_CIRCUITS.len()
+        && inputs.iter().all(|arg| !arg.contains('='))
+    {
+        circuits::MASP_CIRCUITS
+            .iter()
+            .zip(inputs)
+            .map(|(name, path)| (name.to_string(), path.clone()))
+            .collect()
+    } else {
+        usage()
+    };
+    if inputs.is_empty() {
+        usage();
+    }
+
+    let mut ceremony = Ceremony {
+        circuits: vec![],
+        history: vec![],
+        encoding: Encoding::Uncompressed,
+    };
+    for (name, path) in inputs {
+        let label = circuits::label(&name);
+        checks.report(mode, &path);
+        let mut f =
+            progress::open(mode, &path).unwrap_or_else(|e| panic!("couldn't open {}: {}", path, e));
+        let fingerprint = circuits::registered_fingerprint(&name);
+        let limits = unknown
+            .limits(&CircuitEntry {
+                name: name.clone(),
+                fingerprint: fingerprint.clone(),
+            })
+            .unwrap_or_else(|e| panic!("couldn't read {} params: {}", label, e));
+        let params = layout::read_params(&mut f, checks.checked(), limits)
+            .unwrap_or_else(|e| panic!("couldn't deserialize {} params: {}", label, e));
+        f.into_inner().finish();
+
+        println!(
+            "{}: {} contributions from {}",
+            label,
+            params.contributions.len(),
+            path
+        );
+        if !delta_matches_contributions(&params) {
+            println!(
+                "{} params don't end with the contribution that produced them",
+                label
+            );
+            std::process::exit(exitcode::DATAERR);
+        }
+
+        ceremony.circuits.push(CircuitParams {
+            name,
+            fingerprint,
+            params,
+        });
+    }
+
+    let rounds = match combined_hashes(&ceremony.contributions()) {
+        Ok(rounds) => rounds,
+        Err(_) => {
+            println!("The circuits have different numbers of contributions");
+            std::process::exit(exitcode::DATAERR);
+        }
+    };
+
+    if let Some(expect) = expect {
+        let f = File::open(expect).expect("couldn't open expected hashes");
+        let expected: Vec<String> = BufReader::new(f)
+            .lines()
+            .map(|line| line.expect("couldn't read expected hashes"))
+            .map(|line| line.trim().trim_start_matches("0x").to_lowercase())
+            .filter(|line| !line.is_empty())
+            .collect();
+        let actual: Vec<String> = rounds.iter().map(|h| into_hex(h)).collect();
+        if expected != actual {
+            println!("The contributions don't match the expected rounds:");
+            for (i, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+                if expected != actual {
+                    println!("  round {}: expected {}, found {}", i + 1, expected, actual);
+                }
+            }
+            if expected.len() != actual.len() {
+                println!(
+                    "  expected {} rounds, found {}",
+                    expected.len(),
+                    actual.len()
+                );
+            }
+            std::process::exit(exitcode::DATAERR);
+        }
+    }
+
+    let mut f = AtomicFile::create(out_params_filename, force).unwrap_or_else(|e| {
+        println!("couldn't create `{}`: {}", out_params_filename, e);
+        std::process::exit(exitcode::CANTCREAT);
+    });
+    let total = ceremony.serialized_len();
+    let mut writer = progress::writer(mode, out_params_filename, total, &mut f);
+    ceremony
+        .write(&mut writer)
+        .expect("couldn't write joined params");
+    writer.finish();
+    f.commit().expect("couldn't write joined params");
+
+    println!("Wrote {} rounds to {}", rounds.len(), out_params_filename);
+}
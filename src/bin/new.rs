@@ -1,10 +1,18 @@
+use masp_mpc::ceremony::{Ceremony, CircuitParams};
+use masp_mpc::circuits::{self, MaspCircuit};
+use masp_mpc::layout::Encoding;
+use masp_mpc::progress::{self, Mode};
 use masp_phase2::MPCParameters;
 use std::fs::File;
+use std::io::{BufWriter, Write};
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 3 {
-        println!("Usage: \n<out_params.params> <path/to/phase1radix>");
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    if args.len() < 3 {
+        println!(
+            "Usage: \n<out_params.params> <path/to/phase1radix> [circuit ...] [--quiet | --json]"
+        );
         std::process::exit(exitcode::USAGE);
     }
     let params_filename = &args[1];
@@ -12,139 +20,89 @@ fn main() {
 
     //let should_filter_points_at_infinity = false;
 
-    println!("Creating initial parameters for MASP Spend...");
-
-    let mut f = File::create(params_filename).unwrap();
-
-    // MASP spend circuit
-    let spend_params = MPCParameters::new(
-        masp_proofs::circuit::sapling::Spend {
-            value_commitment: None,
-            proof_generation_key: None,
-            payment_address: None,
-            commitment_randomness: None,
-            ar: None,
-            auth_path: vec![None; 32], // Tree depth is 32 for sapling
-            anchor: None,
-        },
-        //should_filter_points_at_infinity,
-        //radix_directory,
-    )
-    .unwrap();
-    println!(
-        "Writing initial MASP Spend parameters to {}.",
-        params_filename
-    );
-
-    spend_params
-        .write(&mut f)
-        .expect("unable to write MASP Spend params");
-
-    println!("Creating initial parameters for MASP Output...");
-
-    // MASP output circuit
-    let output_params = MPCParameters::new(
-        masp_proofs::circuit::sapling::Output {
-            value_commitment: None,
-            payment_address: None,
-            commitment_randomness: None,
-            esk: None,
-            asset_identifier: vec![None; 256],
-        },
-        //should_filter_points_at_infinity,
-        //radix_directory,
-    )
-    .unwrap();
-
-    println!(
-        "Writing initial MASP Output parameters to {}.",
-        params_filename
-    );
+    // The MASP circuits are used unless a list of circuits is given
+    let names: Vec<String> = if args.len() > 3 {
+        args[3..].to_vec()
+    } else {
+        circuits::MASP_CIRCUITS
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    };
+
+    let mut ceremony = Ceremony {
+        circuits: vec![],
+        history: vec![],
+        encoding: Encoding::Uncompressed,
+    };
+    for name in names {
+        let circuit = match MaspCircuit::blank(&name) {
+            Some(circuit) => circuit,
+            None => {
+                println!("Unknown circuit `{}`", name);
+                std::process::exit(exitcode::USAGE);
+            }
+        };
+
+        println!(
+            "Creating initial parameters for {}...",
+            circuits::label(&name)
+        );
+        let params = MPCParameters::new(
+            circuit,
+            //should_filter_points_at_infinity,
+            //radix_directory,
+        )
+        .unwrap();
 
-    output_params
-        .write(&mut f)
-        .expect("unable to write MASP Output params");
+        ceremony.circuits.push(CircuitParams {
+            fingerprint: MaspCircuit::fingerprint(&name),
+            name,
+            params,
+        });
+    }
 
-    // MASP Convert circuit
-    let convert_params = MPCParameters::new(
-        masp_proofs::circuit::convert::Convert {
-            value_commitment: None,
-            auth_path: vec![None; 32], // Tree depth is 32 for sapling
-            anchor: None,
-        },
-        //should_filter_points_at_infinity,
-        //radix_directory,
-    )
-    .unwrap();
-    println!(
-        "Writing initial MASP Convert parameters to {}.",
-        params_filename
-    );
+    println!("Writing initial parameters to {}.", params_filename);
 
-    convert_params
-        .write(&mut f)
-        .expect("unable to write MASP Convert params");
+    let f = File::create(params_filename).unwrap();
+    let f = BufWriter::with_capacity(1024 * 1024, f);
+    let mut writer = progress::writer(mode, params_filename, ceremony.serialized_len(), f);
+    ceremony
+        .write(&mut writer)
+        .and_then(|_| writer.flush())
+        .expect("unable to write initial params");
+    writer.finish();
 }
 
 #[test]
 fn test_hash() {
     use bellman::Circuit;
-    use bls12_381::Bls12;
-    {
-        let mut cs = bellman::gadgets::test::TestConstraintSystem::<Bls12>::new();
-
-        masp_proofs::circuit::sapling::Spend {
-            value_commitment: None,
-            proof_generation_key: None,
-            payment_address: None,
-            commitment_randomness: None,
-            ar: None,
-            auth_path: vec![None; 32], // Tree depth is 32 for sapling
-            anchor: None,
-        }
-        .synthesize(&mut cs)
-        .unwrap();
-
-        assert_eq!(cs.num_constraints(), 100637);
-        assert_eq!(
-            cs.hash(),
-            "34e4a634c80e4e4c6250e63b7855532e60b36d1371d4d7b1163218b69f09eb3d"
-        );
-    }
-    {
-        let mut cs = bellman::gadgets::test::TestConstraintSystem::<Bls12>::new();
-
-        masp_proofs::circuit::sapling::Output {
-            value_commitment: None,
-            payment_address: None,
-            commitment_randomness: None,
-            esk: None,
-            asset_identifier: vec![None; 256],
-        }
-        .synthesize(&mut cs)
-        .unwrap();
-
-        assert_eq!(cs.num_constraints(), 31205);
-        assert_eq!(
-            cs.hash(),
-            "93e445d7858e98c7138558df341f020aedfe75893535025587d64731e244276a"
-        );
-    }
-    {
-        let mut cs = bellman::gadgets::test::TestConstraintSystem::<Bls12>::new();
-
-        masp_proofs::circuit::convert::Convert {
-            value_commitment: None,
-            auth_path: vec![None; 32], // Tree depth is 32 for sapling
-            anchor: None,
-        }
-        .synthesize(&mut cs)
-        .unwrap();
-
-        assert_eq!(cs.num_constraints(), 47358);
-        assert_eq!(
-            cs.hash(),
-            "f74b47ef6e59081548f81f5806bd15b1f4a65d2e57681e6db2b8db7eef2ff814"
-        );
+    use bls12_381::Scalar;
+    for (name, num_constraints, hash) in [
+        (
+            circuits::SPEND,
+            100637,
+            "34e4a634c80e4e4c6250e63b7855532e60b36d1371d4d7b1163218b69f09eb3d",
+        ),
+        (
+            circuits::OUTPUT,
+            31205,
+            "93e445d7858e98c7138558df341f020aedfe75893535025587d64731e244276a",
+        ),
+        (
+            circuits::CONVERT,
+            47358,
+            "f74b47ef6e59081548f81f5806bd15b1f4a65d2e57681e6db2b8db7eef2ff814",
+        ),
+    ] {
+        let mut cs = bellman::gadgets::test::TestConstraintSystem::<Scalar>::new();
+
+        MaspCircuit::blank(name)
+            .unwrap()
+            .synthesize(&mut cs)
+            .unwrap();
+
+        assert_eq!(cs.num_constraints(), num_constraints);
+        assert_eq!(cs.hash(), hash);
     }
 }
@@ -0,0 +1,78 @@
+//! Creates and verifies a proof with the Groth16 parameters of every circuit,
+//! as a functional check on top of verifying the transcript.
+
+use bellman::gadgets::test::TestConstraintSystem;
+use bellman::groth16::{create_random_proof, prepare_verifying_key, verify_proof};
+use bellman::Circuit;
+use masp_mpc::ceremony::{Ceremony, PointChecks, UnknownCircuits};
+use masp_mpc::circuits::{self, MaspCircuit};
+use masp_mpc::progress::Mode;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use std::time::Instant;
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    if args.len() != 2 {
+        println!("Usage: \n<params.params> [--quiet | --json]");
+        std::process::exit(exitcode::USAGE);
+    }
+    let params_filename = &args[1];
+
+    let ceremony = Ceremony::open(
+        mode,
+        params_filename,
+        PointChecks::Full,
+        UnknownCircuits::Reject,
+    )
+    .expect("couldn't deserialize params");
+
+    let mut rng = ChaChaRng::from_rng(rand::rngs::OsRng).unwrap();
+    let mut failed = false;
+    for circuit in &ceremony.circuits {
+        let label = circuits::label(&circuit.name);
+
+        // The circuit is consumed by each step, so every step gets its own
+        // copy of the same witness
+        let seed: [u8; 32] = rng.gen();
+        let witness = || MaspCircuit::random(&circuit.name, &mut ChaChaRng::from_seed(seed));
+        if witness().is_none() {
+            println!("Unknown circuit `{}`", circuit.name);
+            std::process::exit(exitcode::DATAERR);
+        }
+
+        let mut cs = TestConstraintSystem::new();
+        witness()
+            .unwrap()
+            .synthesize(&mut cs)
+            .expect("couldn't synthesize circuit");
+        if let Some(constraint) = cs.which_is_unsatisfied() {
+            panic!("random {} witness doesn't satisfy `{}`", label, constraint);
+        }
+        let inputs = witness()
+            .unwrap()
+            .public_inputs()
+            .expect("couldn't synthesize circuit");
+
+        mode.message(&label, "creating proof");
+        let start = Instant::now();
+        let params = circuit.params.get_params();
+        let proof = create_random_proof(witness().unwrap(), params, &mut rng)
+            .unwrap_or_else(|e| panic!("couldn't create {} proof: {}", label, e));
+        let elapsed = start.elapsed().as_secs_f64();
+
+        let pvk = prepare_verifying_key(&params.vk);
+        match verify_proof(&pvk, &proof, &inputs) {
+            Ok(()) => println!("{} proof created in {:.1}s and verified", label, elapsed),
+            Err(e) => {
+                println!("{} proof is INVALID: {}", label, e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(exitcode::DATAERR);
+    }
+}
@@ -1,50 +1,125 @@
 //! This binary just splits the parameters up into separate files.
 
-use masp_phase2::MPCParameters;
-use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use masp_mpc::ceremony::{PointChecks, UnknownCircuits};
+use masp_mpc::circuits;
+use masp_mpc::fs::AtomicFile;
+use masp_mpc::into_hex;
+use masp_mpc::layout::{self, Encoding};
+use masp_mpc::mmap::MappedCeremony;
+use masp_mpc::progress::{self, Mode};
+use std::io::Write;
+use std::path::PathBuf;
+
+fn usage() -> ! {
+    println!(
+        "Usage: \n<params.params> [--out-dir <dir>] [--prefix <prefix>] [--force] [--skip-point-checks] [--allow-unknown-circuits] [--quiet | --json]"
+    );
+    std::process::exit(exitcode::USAGE);
+}
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: \n<params.params>");
-        std::process::exit(exitcode::USAGE);
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let checks = PointChecks::from_args(&mut args);
+    let unknown = UnknownCircuits::from_args(&mut args);
+    let mut params_filename = None;
+    let mut out_dir = PathBuf::from(".");
+    let mut prefix = "masp-".to_string();
+    let mut force = false;
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--out-dir" => out_dir = rest.next().unwrap_or_else(|| usage()).into(),
+            "--prefix" => prefix = rest.next().unwrap_or_else(|| usage()).clone(),
+            "--force" => force = true,
+            _ if params_filename.is_none() => params_filename = Some(arg),
+            _ => usage(),
+        }
     }
-    let current_params = File::open(&args[1]).expect("couldn't open params");
-    let mut current_params = BufReader::with_capacity(1024 * 1024, current_params);
-    current_params.seek_relative(64).unwrap();
+    let params_filename = params_filename.unwrap_or_else(|| usage());
 
-    let masp_spend = MPCParameters::read(&mut current_params, false)
-        .expect("couldn't deserialize MASP Spend params");
+    // Only one circuit is deserialized at a time, if any
+    let ceremony = MappedCeremony::open(params_filename).expect("couldn't open params");
+    checks.report(mode, params_filename);
 
-    let masp_output = MPCParameters::read(&mut current_params, false)
-        .expect("couldn't deserialize MASP Output params");
+    let manifest_path = out_dir.join(format!("{}manifest.txt", prefix));
+    let paths: Vec<PathBuf> = ceremony
+        .circuits()
+        .iter()
+        .map(|circuit| out_dir.join(format!("{}{}.params", prefix, circuit.name())))
+        .collect();
+
+    // Refuse before writing anything, rather than leaving a partial set behind
+    if !force {
+        for path in paths.iter().chain(Some(&manifest_path)) {
+            if path.exists() {
+                println!(
+                    "`{}` already exists, pass --force to overwrite",
+                    path.display()
+                );
+                std::process::exit(exitcode::CANTCREAT);
+            }
+        }
+    }
 
-    let masp_convert = MPCParameters::read(&mut current_params, false)
-        .expect("couldn't deserialize MASP Convert params");
+    // Nothing is moved into place until every circuit has been read
+    let mut files = vec![];
+    for (circuit, path) in ceremony.circuits().iter().zip(paths) {
+        let label = circuits::label(circuit.name());
 
-    {
-        let f = File::create("masp-spend.params").expect("couldn't create `./masp-spend.params`");
-        let mut f = BufWriter::with_capacity(1024 * 1024, f);
-        masp_spend
-            .write(&mut f)
-            .expect("couldn't write new MASP Spend params");
+        let mut f = AtomicFile::create(&path, force).unwrap_or_else(|e| {
+            println!("couldn't create `{}`: {}", path.display(), e);
+            std::process::exit(exitcode::CANTCREAT);
+        });
+        // Unchecked uncompressed parameters are copied as they are, once
+        // their circuit is known to be the registered one
+        match (checks, circuit.encoding) {
+            (PointChecks::Skip, Encoding::Uncompressed) => {
+                circuit
+                    .check_limits(unknown)
+                    .unwrap_or_else(|e| panic!("couldn't read {} params: {}", label, e));
+                f.write_all(circuit.bytes())
+            }
+            _ => {
+                let params = circuit
+                    .read_params(checks.checked(), unknown)
+                    .unwrap_or_else(|e| panic!("couldn't deserialize {} params: {}", label, e));
+                let total = layout::serialized_len(&params);
+                let mut writer = progress::writer(mode, &path, total, &mut f);
+                params.write(&mut writer).map(|()| {
+                    writer.finish();
+                })
+            }
+        }
+        .unwrap_or_else(|e| panic!("couldn't write new {} params: {}", label, e));
+        files.push((label, f));
     }
 
-    {
-        let f = File::create("masp-output.params").expect("couldn't create `./masp-output.params`");
-        let mut f = BufWriter::with_capacity(1024 * 1024, f);
-        masp_output
-            .write(&mut f)
-            .expect("couldn't write new MASP Output params");
+    let mut summaries = vec![];
+    for (label, f) in files {
+        let summary = f
+            .commit()
+            .unwrap_or_else(|e| panic!("couldn't write new {} params: {}", label, e));
+        println!("Wrote {} params to {}", label, summary.path.display());
+        summaries.push(summary);
     }
 
-    {
-        let f =
-            File::create("masp-convert.params").expect("couldn't create `./masp-convert.params`");
-        let mut f = BufWriter::with_capacity(1024 * 1024, f);
-        masp_convert
-            .write(&mut f)
-            .expect("couldn't write new MASP Convert params");
+    let mut manifest = AtomicFile::create(&manifest_path, force).unwrap_or_else(|e| {
+        println!("couldn't create `{}`: {}", manifest_path.display(), e);
+        std::process::exit(exitcode::CANTCREAT);
+    });
+    for summary in &summaries {
+        let name = summary.path.file_name().unwrap().to_string_lossy();
+        writeln!(
+            manifest,
+            "{} {} {}",
+            name,
+            summary.len,
+            into_hex(&summary.hash)
+        )
+        .expect("couldn't write manifest");
     }
+    manifest.commit().expect("couldn't write manifest");
+    println!("Wrote manifest to {}", manifest_path.display());
 }
@@ -1,95 +1,219 @@
-use blake2::{Blake2b512, Digest};
-use masp_phase2::MPCParameters;
-use std::fs::File;
-use std::io::BufReader;
+use masp_mpc::batch;
+use masp_mpc::cache::VerificationCache;
+use masp_mpc::ceremony::{combined_hashes, Ceremony, CircuitParams, PointChecks, UnknownCircuits};
+use masp_mpc::circuits::{self, MaspCircuit};
+use masp_mpc::fs::file_digest;
+use masp_mpc::into_hex;
+use masp_mpc::progress::Mode;
+use masp_phase2::verify_contribution;
+use std::sync::Mutex;
+use std::time::Instant;
+
+fn usage() -> ! {
+    println!("Usage: \n<params.params> <path/to/phase1radix> [--cache <cache.txt> [--previous <prev_params.params>]] [--threads <n>] [--quiet | --json]");
+    std::process::exit(exitcode::USAGE);
+}
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 3 {
-        println!("Usage: \n<params.params> <path/to/phase1radix>");
-        std::process::exit(exitcode::USAGE);
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let mut positional = vec![];
+    let mut cache_path = None;
+    let mut previous = None;
+    let mut threads = batch::default_threads();
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--cache" => cache_path = Some(rest.next().unwrap_or_else(|| usage())),
+            "--previous" => previous = Some(rest.next().unwrap_or_else(|| usage())),
+            "--threads" => {
+                threads = match rest.next().map(|n| n.parse()) {
+                    Some(Ok(n)) if n > 0 => n,
+                    _ => usage(),
+                }
+            }
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() != 2 || (previous.is_some() && cache_path.is_none()) {
+        usage();
+    }
+    let params_filename = positional[0];
+    //let radix_directory = positional[1];
+
+    //let should_filter_points_at_infinity = false;
+
+    // Also bounds bellman's own thread pool, used to create the initial
+    // parameters of each circuit
+    std::env::set_var("BELLMAN_NUM_CPUS", threads.to_string());
+
+    let digest = file_digest(params_filename).expect("couldn't hash params");
+    let cached = cache_path
+        .and_then(|path| VerificationCache::load(path).expect("couldn't read verification cache"));
+
+    let cache = match cached {
+        Some(cache) if cache.head() == Some(digest) => {
+            mode.message(params_filename, "already verified");
+            cache
+        }
+        Some(cache) if previous.is_some() => {
+            let previous = previous.unwrap();
+            if cache.head() == Some(file_digest(previous).expect("couldn't hash previous params")) {
+                extend(mode, cache, previous, params_filename, digest)
+                    .unwrap_or_else(|| verify(mode, params_filename, digest, threads))
+            } else {
+                mode.message(
+                    previous,
+                    "not the last verified round, verifying the whole chain",
+                );
+                verify(mode, params_filename, digest, threads)
+            }
+        }
+        _ => verify(mode, params_filename, digest, threads),
+    };
+
+    if let Some(path) = cache_path {
+        cache.save(path).expect("couldn't write verification cache");
+    }
+
+    for h in combined_hashes(&cache.contributions()).expect("inconsistent contributions") {
+        println!("{}", into_hex(&h));
+    }
+}
+
+/// Verifies every circuit's whole chain of contributions, running up to
+/// `threads` circuits at once and sharing the threads between them.
+fn verify(
+    mode: Mode,
+    params_filename: &str,
+    digest: [u8; 64],
+    threads: usize,
+) -> VerificationCache {
+    let ceremony = Ceremony::open(
+        mode,
+        params_filename,
+        PointChecks::Full,
+        UnknownCircuits::Reject,
+    )
+    .expect("couldn't deserialize params");
+
+    // Look every circuit up before spending time on any of them
+    let mut blanks = vec![];
+    for circuit in &ceremony.circuits {
+        match MaspCircuit::blank(&circuit.name) {
+            Some(blank) => blanks.push(blank),
+            None => {
+                println!("Unknown circuit `{}`", circuit.name);
+                std::process::exit(exitcode::DATAERR);
+            }
+        }
     }
-    let params_filename = &args[1];
-    let radix_directory = &args[2];
-
-    let should_filter_points_at_infinity = false;
-
-    let current_params = File::open(params_filename).expect("couldn't open params");
-    let mut current_params = BufReader::with_capacity(1024 * 1024, current_params);
-    current_params.seek_relative(64).unwrap();
-
-    let masp_spend = MPCParameters::read(&mut current_params, true)
-        .expect("couldn't deserialize MASP Spend params");
-
-    let masp_output = MPCParameters::read(&mut current_params, true)
-        .expect("couldn't deserialize MASP Output params");
-
-    let masp_convert = MPCParameters::read(&mut current_params, true)
-        .expect("couldn't deserialize MASP Convert params");
-
-    let masp_spend_contributions = masp_spend
-        .verify(
-            masp_proofs::circuit::sapling::Spend {
-                value_commitment: None,
-                proof_generation_key: None,
-                payment_address: None,
-                commitment_randomness: None,
-                ar: None,
-                auth_path: vec![None; 32], // Tree depth is 32 for sapling
-                anchor: None,
-            },
-            //should_filter_points_at_infinity,
-            //radix_directory,
-        )
-        .expect("MASP Spend parameters are invalid");
-
-    let masp_output_contributions = masp_output
-        .verify(
-            masp_proofs::circuit::sapling::Output {
-                value_commitment: None,
-                payment_address: None,
-                commitment_randomness: None,
-                esk: None,
-                asset_identifier: vec![None; 256],
-            },
-            //should_filter_points_at_infinity,
-            //radix_directory,
-        )
-        .expect("MASP Output parameters are invalid");
-
-    let masp_convert_contributions = masp_convert
-        .verify(
-            masp_proofs::circuit::convert::Convert {
-                value_commitment: None,
-                auth_path: vec![None; 32], // Tree depth is 32 for sapling
-                anchor: None,
-            },
-            //should_filter_points_at_infinity,
-            //radix_directory,
-        )
-        .expect("MASP Convert parameters are invalid");
-
-    for (spend_hash, output_hash, convert_hash) in itertools::multizip((
-        masp_spend_contributions.into_iter(),
-        masp_output_contributions.into_iter(),
-        masp_convert_contributions.into_iter(),
-    )) {
-        let mut h = Blake2b512::new();
-        h.update(&spend_hash);
-        h.update(&output_hash);
-        h.update(&convert_hash);
-        let h = h.finalize();
-
-        println!("{}", into_hex(h.as_ref()));
+
+    let concurrent = threads.min(blanks.len()).max(1);
+    let threads_per_circuit = (threads / concurrent).max(1);
+
+    // Each worker takes the next circuit as soon as it's done with one
+    let jobs = Mutex::new(ceremony.circuits.iter().zip(blanks).enumerate());
+    let results = Mutex::new(vec![None; ceremony.circuits.len()]);
+    std::thread::scope(|scope| {
+        for _ in 0..concurrent {
+            scope.spawn(|| loop {
+                let job = jobs.lock().unwrap().next();
+                let (i, (circuit, blank)) = match job {
+                    Some(job) => job,
+                    None => break,
+                };
+                let result = verify_circuit(mode, circuit, blank, threads_per_circuit);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    let mut contributions = vec![];
+    for (circuit, result) in ceremony.circuits.iter().zip(results.into_inner().unwrap()) {
+        let label = circuits::label(&circuit.name);
+        contributions.push(
+            result
+                .expect("circuit wasn't verified")
+                .unwrap_or_else(|_| panic!("{} parameters are invalid", label)),
+        );
+    }
+
+    let names = ceremony.circuits.into_iter().map(|c| c.name).collect();
+    VerificationCache::new(names, &contributions, digest).expect("inconsistent contributions")
+}
+
+/// Verifies one circuit, reporting each stage.
+fn verify_circuit(
+    mode: Mode,
+    circuit: &CircuitParams,
+    blank: MaspCircuit,
+    threads: usize,
+) -> Result<Vec<[u8; 64]>, ()> {
+    let label = circuits::label(&circuit.name);
+    let start = Instant::now();
+
+    let result = batch::verify_with(
+        &circuit.params,
+        blank,
+        threads,
+        //should_filter_points_at_infinity,
+        //radix_directory,
+        batch::reporter(mode, label.clone()),
+    );
+    if result.is_ok() {
+        let elapsed = start.elapsed().as_secs_f64();
+        mode.message(&label, &format!("verified in {:.1}s", elapsed));
     }
+
+    result
 }
 
-fn into_hex(h: &[u8]) -> String {
-    let mut f = String::new();
+/// Verifies the transformation from `previous`, the last round in `cache`, to
+/// the parameters in `params_filename` and adds it to the cache. Returns
+/// `None` if the cache is for other circuits, so the whole chain needs
+/// verifying.
+fn extend(
+    mode: Mode,
+    mut cache: VerificationCache,
+    previous: &str,
+    params_filename: &str,
+    digest: [u8; 64],
+) -> Option<VerificationCache> {
+    // Its digest matches a verified file, so the previous round needs no checks
+    let before = Ceremony::open(mode, previous, PointChecks::Skip, UnknownCircuits::Reject)
+        .expect("couldn't deserialize previous params");
+    let after = Ceremony::open(
+        mode,
+        params_filename,
+        PointChecks::Full,
+        UnknownCircuits::Reject,
+    )
+    .expect("couldn't deserialize params");
+
+    if before.header().circuits != after.header().circuits {
+        panic!("previous params and params contain different circuits!");
+    }
+    let names: Vec<String> = after.circuits.iter().map(|c| c.name.clone()).collect();
+    if let Err(e) = cache.check_circuits(&names) {
+        mode.message(
+            params_filename,
+            &format!("{}, verifying the whole chain", e),
+        );
+        return None;
+    }
 
-    for byte in &h[..] {
-        f += &format!("{:02x}", byte);
+    let mut hashes = vec![];
+    for (before, after) in before.circuits.iter().zip(after.circuits.iter()) {
+        match verify_contribution(&before.params, &after.params) {
+            Ok(hash) => hashes.push(hash),
+            Err(_) => panic!("invalid {} transformation!", circuits::label(&before.name)),
+        }
     }
 
-    f
+    cache
+        .push(digest, hashes)
+        .expect("couldn't extend verification cache");
+    Some(cache)
 }
@@ -1,165 +1,207 @@
-use blake2::{Blake2b512, Digest};
-use masp_phase2::MPCParameters;
-use std::fs::File;
-use std::io::{BufReader, Read};
+use masp_mpc::batch;
+use masp_mpc::ceremony::{combined_hashes, Ceremony, PointChecks, UnknownCircuits};
+use masp_mpc::circuits::{self, MaspCircuit};
+use masp_mpc::fs::file_digest;
+use masp_mpc::mmap::MappedCeremony;
+use masp_mpc::progress::Mode;
+use masp_mpc::{into_hex, parse_hash};
+use std::path::Path;
+
+/// Digest of the params file a round wrote.
+#[derive(Clone, Copy)]
+enum FileDigest {
+    /// Computed from a file given on the command line.
+    Computed([u8; 64]),
+    /// Recorded in the final file's history.
+    Recorded([u8; 64]),
+}
+
+/// Contributions recorded in a params file.
+struct Contributions {
+    names: Vec<String>,
+    /// File digests of previous rounds from the header, which nothing
+    /// verifies: anyone can rewrite them without invalidating the parameters.
+    history: Vec<[u8; 64]>,
+    /// Contribution hashes per circuit.
+    hashes: Vec<Vec<[u8; 64]>>,
+}
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 3 {
-        println!("Usage: \ncontribution_to_check final_contribution");
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let full = args.len() == 4 && args[1] == "--full";
+    if args.len() != 3 && !full {
+        println!("Usage: \n[--full] <contribution_to_check | hash> final_contribution [--quiet | --json]");
+        println!("hash is either the contribution hash printed by `contribute` or the file digest of its output");
+        println!(
+            "--full verifies the final parameters instead of only looking up the contribution"
+        );
         std::process::exit(exitcode::USAGE);
     }
-    let contribution_to_check = &args[1];
-    let final_contribution = &args[2];
-
-    let current_params = File::open(contribution_to_check).expect("couldn't open params");
-    let mut current_params = BufReader::with_capacity(1024 * 1024, current_params);
-    current_params.seek_relative(64).unwrap();
-    let contribution_hash = hash_reader(64, current_params);
+    let contribution_to_check = &args[args.len() - 2];
+    let final_contribution = &args[args.len() - 1];
+
+    let contributions = extract_contributions(mode, final_contribution, full);
+    let final_internal_hashes =
+        combined_hashes(&contributions.hashes).expect("inconsistent contributions");
+    if full {
+        println!(
+            "Final parameters are valid, {} rounds verified",
+            final_internal_hashes.len()
+        );
+    }
+    let rounds = final_internal_hashes.len();
 
-    let ctc = extract_internal_hashes(contribution_to_check, false);
-    let target_internal_hash = ctc
-        .last()
-        .expect("At least one contribution needed in contribution_to_check");
-    let final_internal_hashes = extract_internal_hashes(final_contribution, false);
+    // Digest of the params file written by each round, where known
+    let mut file_digests: Vec<Option<FileDigest>> = vec![None; rounds + 1];
+    for (i, digest) in contributions.history.iter().rev().enumerate() {
+        if let Some(round) = rounds.checked_sub(i + 1) {
+            file_digests[round] = Some(FileDigest::Recorded(*digest));
+        }
+    }
+    file_digests[rounds] = Some(FileDigest::Computed(
+        file_digest(final_contribution).expect("couldn't hash params"),
+    ));
+
+    let (key, round) = match parse_hash(contribution_to_check) {
+        Some(hash) if !Path::new(contribution_to_check).exists() => {
+            // A contribution hash, or else the digest of a round's params
+            // file. Verifying the parameters says nothing of the history, so
+            // with --full only the final file's own digest is looked up.
+            let round = match final_internal_hashes.iter().position(|h| *h == hash) {
+                Some(i) => Some(i + 1),
+                None => file_digests.iter().position(|d| match d {
+                    Some(FileDigest::Computed(digest)) => *digest == hash,
+                    Some(FileDigest::Recorded(digest)) => !full && *digest == hash,
+                    None => false,
+                }),
+            };
+            (into_hex(&hash), round)
+        }
+        _ => {
+            let ctc = extract_contributions(mode, contribution_to_check, false);
+            let ctc = combined_hashes(&ctc.hashes).expect("inconsistent contributions");
+            let target_internal_hash = *ctc
+                .last()
+                .expect("At least one contribution needed in contribution_to_check");
+            let digest = file_digest(contribution_to_check).expect("couldn't hash params");
+
+            println!("{}", contribution_to_check);
+            println!("  Contribution hash: {}", into_hex(&target_internal_hash));
+            println!("  File digest:       {}", into_hex(&digest));
+
+            let round = final_internal_hashes
+                .iter()
+                .position(|h| *h == target_internal_hash)
+                .map(|i| i + 1);
+            if let Some(round) = round {
+                file_digests[round] = Some(FileDigest::Computed(digest));
+            }
+            (contribution_to_check.clone(), round)
+        }
+    };
 
-    for (i, internal_hash) in final_internal_hashes.iter().enumerate() {
-        if internal_hash == target_internal_hash {
+    match round {
+        Some(0) => {
             println!(
-                "Contribution with contribution hash {} found at round {}",
-                contribution_hash,
-                i + 1
+                "{} is the file digest of the initial parameters, not of a contribution",
+                key
             );
-            std::process::exit(0);
+        }
+        Some(round) => {
+            println!("Contribution {} found at round {}", key, round);
+            println!(
+                "  Contribution hash: {}",
+                into_hex(&final_internal_hashes[round - 1])
+            );
+            match file_digests[round] {
+                Some(FileDigest::Computed(digest)) => {
+                    println!("  File digest:       {}", into_hex(&digest))
+                }
+                Some(FileDigest::Recorded(digest)) => println!(
+                    "  File digest:       {} (from the file history, unverified)",
+                    into_hex(&digest)
+                ),
+                None => println!("  File digest:       unknown"),
+            }
+            for (name, hashes) in contributions.names.iter().zip(&contributions.hashes) {
+                println!(
+                    "  {}: {}",
+                    circuits::label(name),
+                    into_hex(&hashes[round - 1])
+                );
+            }
+        }
+        None => {
+            println!("Contribution {} not found", key);
+            if full {
+                println!("With --full, only the final file's digest is looked up, as its history isn't verified");
+            }
         }
     }
-    println!(
-        "Contribution with contribution hash {} not found",
-        contribution_hash
-    );
-}
-
-fn into_hex(h: &[u8]) -> String {
-    let mut f = String::new();
-
-    for byte in &h[..] {
-        f += &format!("{:02x}", byte);
-    }
-
-    f
 }
 
-fn extract_internal_hashes(params_filename: &str, checked: bool) -> Vec<[u8; 64]> {
-    let current_params = File::open(params_filename).expect("couldn't open params");
-    let mut current_params = BufReader::with_capacity(1024 * 1024, current_params);
-    current_params.seek_relative(64).unwrap();
-
-    let masp_spend = MPCParameters::read(&mut current_params, checked)
-        .expect("couldn't deserialize MASP Spend params");
-
-    let masp_output = MPCParameters::read(&mut current_params, checked)
-        .expect("couldn't deserialize MASP Output params");
-
-    let masp_convert = MPCParameters::read(&mut current_params, checked)
-        .expect("couldn't deserialize MASP Convert params");
-
+/// Returns the contributions recorded in a params file, verifying the
+/// parameters first if `checked` is set.
+fn extract_contributions(mode: Mode, params_filename: &str, checked: bool) -> Contributions {
     let verify_params = checked;
-    let masp_spend_contributions = if !verify_params {
-        extract_contributions(&masp_spend)
-    } else {
-        masp_spend
-            .verify(masp_proofs::circuit::sapling::Spend {
-                value_commitment: None,
-                proof_generation_key: None,
-                payment_address: None,
-                commitment_randomness: None,
-                ar: None,
-                auth_path: vec![None; 32], // Tree depth is 32 for sapling
-                anchor: None,
-            })
-            .expect("MASP Spend parameters are invalid")
-    };
-
-    let masp_output_contributions = if !verify_params {
-        extract_contributions(&masp_output)
-    } else {
-        masp_output
-            .verify(masp_proofs::circuit::sapling::Output {
-                value_commitment: None,
-                payment_address: None,
-                commitment_randomness: None,
-                esk: None,
-                asset_identifier: vec![None; 256],
-            })
-            .expect("MASP Output parameters are invalid")
-    };
-
-    let masp_convert_contributions = if !verify_params {
-        extract_contributions(&masp_convert)
+    if !verify_params {
+        // Only the contribution lists are needed, so the points are never
+        // paged in
+        PointChecks::Skip.report(mode, params_filename);
+        let current_params = MappedCeremony::open(params_filename).expect("couldn't open params");
+        let header = current_params.header();
+        Contributions {
+            names: header.circuits.iter().map(|c| c.name.clone()).collect(),
+            history: header.history.clone(),
+            hashes: current_params.contributions(),
+        }
     } else {
-        masp_convert
-            .verify(masp_proofs::circuit::convert::Convert {
-                value_commitment: None,
-                auth_path: vec![None; 32], // Tree depth is 32 for sapling
-                anchor: None,
-            })
-            .expect("MASP Convert parameters are invalid")
-    };
-    let mut internal_hashes = vec![];
-    for (spend_hash, output_hash, convert_hash) in itertools::multizip((
-        masp_spend_contributions.into_iter(),
-        masp_output_contributions.into_iter(),
-        masp_convert_contributions.into_iter(),
-    )) {
-        let mut h = Blake2b512::new();
-        h.update(&spend_hash);
-        h.update(&output_hash);
-        h.update(&convert_hash);
-        let h = h.finalize();
-        internal_hashes.push(h.into());
-    }
-    internal_hashes
-}
-
-fn extract_contributions(params: &MPCParameters) -> Vec<[u8; 64]> {
-    params
-        .contributions
-        .iter()
-        .map(|pubkey| {
-            let sink = std::io::sink();
-            let mut sink = masp_phase2::HashWriter::new(sink);
-            pubkey.write(&mut sink).unwrap();
-            let h = sink.into_hash();
-            let mut response = [0u8; 64];
-            response.copy_from_slice(h.as_ref());
-            response
-        })
-        .collect()
-}
-
-/// Below code from b2sum crate, MIT License Copyright (c) 2017 John Downey
-fn hash_reader<R>(length: usize, mut reader: R) -> String
-where
-    R: std::io::BufRead,
-{
-    let mut digest = blake2b_simd::Params::new().hash_length(length).to_state();
-
-    loop {
-        let count = {
-            let data = reader.fill_buf().unwrap();
-            if data.is_empty() {
-                break;
+        // Every contribution hash comes out of a fully verified chain, so a
+        // public key copied into otherwise invalid parameters is rejected
+        let ceremony = match Ceremony::open(
+            mode,
+            params_filename,
+            PointChecks::Full,
+            UnknownCircuits::Reject,
+        ) {
+            Ok(ceremony) => ceremony,
+            Err(e) => {
+                println!("{} is not a valid params file: {}", params_filename, e);
+                std::process::exit(exitcode::DATAERR);
             }
-
-            digest.update(data);
-            data.len()
         };
 
-        reader.consume(count);
+        let mut hashes = vec![];
+        for circuit in &ceremony.circuits {
+            let label = circuits::label(&circuit.name);
+            let blank = match MaspCircuit::blank(&circuit.name) {
+                Some(blank) => blank,
+                None => {
+                    println!("Unknown circuit {}, can't verify it", label);
+                    std::process::exit(exitcode::DATAERR);
+                }
+            };
+
+            println!("Verifying {} parameters in {}...", label, params_filename);
+            let threads = batch::default_threads();
+            match batch::verify_with(
+                &circuit.params,
+                blank,
+                threads,
+                batch::reporter(mode, label.clone()),
+            ) {
+                Ok(h) => hashes.push(h),
+                Err(()) => {
+                    println!("{} parameters in {} are INVALID", label, params_filename);
+                    std::process::exit(exitcode::DATAERR);
+                }
+            }
+        }
+        Contributions {
+            names: ceremony.circuits.iter().map(|c| c.name.clone()).collect(),
+            history: ceremony.history,
+            hashes,
+        }
     }
-
-    let output = digest.finalize();
-    let result = output.to_hex().to_ascii_lowercase();
-
-    result
 }
@@ -1,69 +1,34 @@
-use blake2::{Blake2b512, Digest};
-use masp_phase2::{verify_contribution, MPCParameters};
-use std::fs::File;
-use std::io::BufReader;
+use masp_mpc::ceremony::{combined_hash, Ceremony, PointChecks, UnknownCircuits};
+use masp_mpc::circuits;
+use masp_mpc::into_hex;
+use masp_mpc::progress::Mode;
+use masp_phase2::verify_contribution;
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode = Mode::from_args(&mut args);
+    let unknown = UnknownCircuits::from_args(&mut args);
     if args.len() != 3 {
-        println!("Usage: \n<in_params.params> <out_params.params>");
+        println!("Usage: \n<in_params.params> <out_params.params> [--allow-unknown-circuits] [--quiet | --json]");
         std::process::exit(exitcode::USAGE);
     }
-    let params = File::open(&args[1]).unwrap();
-    let mut params = BufReader::with_capacity(1024 * 1024, params);
-    params.seek_relative(64).unwrap();
-
-    let new_params = File::open(&args[2]).unwrap();
-    let mut new_params = BufReader::with_capacity(1024 * 1024, new_params);
-    params.seek_relative(64).unwrap();
-
-    let masp_spend =
-        MPCParameters::read(&mut params, false).expect("couldn't deserialize MASP Spend params");
-
-    let masp_output =
-        MPCParameters::read(&mut params, false).expect("couldn't deserialize MASP Output params");
-    let masp_convert =
-        MPCParameters::read(&mut params, false).expect("couldn't deserialize MASP Convert params");
-
-    let new_masp_spend = MPCParameters::read(&mut new_params, true)
-        .expect("couldn't deserialize MASP Spend new_params");
-
-    let new_masp_output = MPCParameters::read(&mut new_params, true)
-        .expect("couldn't deserialize MASP Output new_params");
-
-    let new_masp_convert = MPCParameters::read(&mut new_params, true)
-        .expect("couldn't deserialize MASP Convert new_params");
-
-    let spend_hash = match verify_contribution(&masp_spend, &new_masp_spend) {
-        Ok(hash) => hash,
-        Err(_) => panic!("invalid MASP Spend transformation!"),
-    };
-
-    let output_hash = match verify_contribution(&masp_output, &new_masp_output) {
-        Ok(hash) => hash,
-        Err(_) => panic!("invalid MASP Output transformation!"),
-    };
-
-    let convert_hash = match verify_contribution(&masp_convert, &new_masp_convert) {
-        Ok(hash) => hash,
-        Err(_) => panic!("invalid MASP Convert transformation!"),
-    };
-
-    let mut h = Blake2b512::new();
-    h.update(&spend_hash);
-    h.update(&output_hash);
-    h.update(&convert_hash);
-    let h = h.finalize();
-
-    println!("{}", into_hex(h.as_ref()));
-}
-
-fn into_hex(h: &[u8]) -> String {
-    let mut f = String::new();
+    // Either file may come from someone else, so both are checked
+    let ceremony = Ceremony::open(mode, &args[1], PointChecks::Full, unknown)
+        .expect("couldn't deserialize params");
+    let new_ceremony = Ceremony::open(mode, &args[2], PointChecks::Full, unknown)
+        .expect("couldn't deserialize new_params");
+
+    if ceremony.header().circuits != new_ceremony.header().circuits {
+        panic!("params and new_params contain different circuits!");
+    }
 
-    for byte in &h[..] {
-        f += &format!("{:02x}", byte);
+    let mut hashes = vec![];
+    for (before, after) in ceremony.circuits.iter().zip(new_ceremony.circuits.iter()) {
+        match verify_contribution(&before.params, &after.params) {
+            Ok(hash) => hashes.push(hash),
+            Err(_) => panic!("invalid {} transformation!", circuits::label(&before.name)),
+        }
     }
 
-    f
+    println!("{}", into_hex(&combined_hash(&hashes)));
 }
@@ -0,0 +1,223 @@
+//! Cache of verified ceremony rounds.
+//!
+//! Verifying the whole transcript with `MPCParameters::verify` gets more
+//! expensive with every round. Once a params file has been verified, the
+//! cache remembers its file digest and contribution hashes, so the next
+//! round only needs its transformation from that file checked.
+//!
+//! The cache is a text file:
+//!
+//! ```text
+//! circuits <name> ...
+//! <round> <file digest or -> <contribution hash per circuit> ...
+//! ```
+//!
+//! Round 0 is the initial parameters and has no contribution hashes. The file
+//! digest is only known for rounds whose params file was verified.
+
+use crate::ceremony::{combined_hashes, invalid_data};
+use crate::fs::AtomicFile;
+use crate::{into_hex, parse_hash};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// One verified round.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Round {
+    /// Digest of the params file written by this round, if it was verified.
+    pub file_digest: Option<[u8; 64]>,
+    /// Contribution hash of this round for each circuit.
+    pub hashes: Vec<[u8; 64]>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerificationCache {
+    pub circuits: Vec<String>,
+    /// Rounds, starting with the initial parameters.
+    pub rounds: Vec<Round>,
+}
+
+impl VerificationCache {
+    /// Builds a cache from fully verified parameters, whose params file has
+    /// digest `file_digest`. Fails unless every circuit has as many
+    /// contributions.
+    pub fn new(
+        circuits: Vec<String>,
+        contributions: &[Vec<[u8; 64]>],
+        file_digest: [u8; 64],
+    ) -> io::Result<VerificationCache> {
+        let count = combined_hashes(contributions)?.len();
+        let mut rounds: Vec<Round> = (0..=count)
+            .map(|round| Round {
+                file_digest: None,
+                hashes: match round {
+                    0 => vec![],
+                    _ => contributions.iter().map(|c| c[round - 1]).collect(),
+                },
+            })
+            .collect();
+        rounds[count].file_digest = Some(file_digest);
+
+        Ok(VerificationCache { circuits, rounds })
+    }
+
+    /// Loads the cache at `path`, if there is one.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Option<VerificationCache>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut lines = contents.lines();
+
+        let circuits: Vec<String> = match lines.next().and_then(|l| l.strip_prefix("circuits")) {
+            Some(names) => names.split_whitespace().map(|w| w.to_string()).collect(),
+            None => return Err(invalid_data("malformed verification cache")),
+        };
+
+        let mut rounds = vec![];
+        for line in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let expected_len = if rounds.is_empty() {
+                2
+            } else {
+                2 + circuits.len()
+            };
+            if words.len() != expected_len || words[0] != rounds.len().to_string() {
+                return Err(invalid_data("malformed verification cache"));
+            }
+
+            let file_digest = match words[1] {
+                "-" => None,
+                digest => {
+                    Some(parse_hash(digest).ok_or_else(|| invalid_data("malformed file digest"))?)
+                }
+            };
+            let hashes = words[2..]
+                .iter()
+                .map(|h| parse_hash(h).ok_or_else(|| invalid_data("malformed contribution hash")))
+                .collect::<io::Result<_>>()?;
+
+            rounds.push(Round {
+                file_digest,
+                hashes,
+            });
+        }
+        if rounds.is_empty() {
+            return Err(invalid_data("malformed verification cache"));
+        }
+
+        Ok(Some(VerificationCache { circuits, rounds }))
+    }
+
+    /// Atomically replaces the cache at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut f = AtomicFile::create(path, true)?;
+
+        writeln!(f, "circuits {}", self.circuits.join(" "))?;
+        for (i, round) in self.rounds.iter().enumerate() {
+            write!(
+                f,
+                "{} {}",
+                i,
+                round.file_digest.map_or("-".to_string(), |d| into_hex(&d))
+            )?;
+            for hash in &round.hashes {
+                write!(f, " {}", into_hex(hash))?;
+            }
+            writeln!(f)?;
+        }
+
+        f.commit().map(|_| ())
+    }
+
+    /// Digest of the most recent verified params file.
+    pub fn head(&self) -> Option<[u8; 64]> {
+        self.rounds.last().and_then(|round| round.file_digest)
+    }
+
+    /// Contribution hashes per circuit, as returned by `MPCParameters::verify`.
+    pub fn contributions(&self) -> Vec<Vec<[u8; 64]>> {
+        (0..self.circuits.len())
+            .map(|i| self.rounds[1..].iter().map(|r| r.hashes[i]).collect())
+            .collect()
+    }
+
+    /// Checks that the cache is for the circuits `names`, in that order.
+    pub fn check_circuits(&self, names: &[String]) -> io::Result<()> {
+        if self.circuits != names {
+            return Err(invalid_data(format!(
+                "verification cache is for circuits `{}`, not `{}`",
+                self.circuits.join(" "),
+                names.join(" ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Appends a round verified against the current head, with one
+    /// contribution hash per circuit.
+    pub fn push(&mut self, file_digest: [u8; 64], hashes: Vec<[u8; 64]>) -> io::Result<()> {
+        if hashes.len() != self.circuits.len() {
+            return Err(invalid_data(format!(
+                "{} contribution hashes for {} circuits",
+                hashes.len(),
+                self.circuits.len()
+            )));
+        }
+        self.rounds.push(Round {
+            file_digest: Some(file_digest),
+            hashes,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_round_trip() {
+        let path = std::env::temp_dir().join(format!("masp-mpc-cache-{}", std::process::id()));
+        assert_eq!(VerificationCache::load(&path).unwrap(), None);
+
+        let contributions = vec![vec![[1u8; 64], [2u8; 64]], vec![[3u8; 64], [4u8; 64]]];
+        let mut cache = VerificationCache::new(
+            vec!["spend".into(), "output".into()],
+            &contributions,
+            [9u8; 64],
+        )
+        .unwrap();
+        assert_eq!(cache.rounds.len(), 3);
+        assert_eq!(cache.head(), Some([9u8; 64]));
+        assert_eq!(cache.contributions(), contributions);
+
+        cache.push([8u8; 64], vec![[5u8; 64], [6u8; 64]]).unwrap();
+        assert!(cache.push([7u8; 64], vec![[5u8; 64]]).is_err());
+        assert!(cache
+            .check_circuits(&["spend".into(), "output".into()])
+            .is_ok());
+        assert!(cache
+            .check_circuits(&["output".into(), "spend".into()])
+            .is_err());
+        cache.save(&path).unwrap();
+        assert_eq!(VerificationCache::load(&path).unwrap(), Some(cache));
+
+        fs::remove_file(&path).unwrap();
+
+        // Every circuit must have the same number of contributions
+        for uneven in [
+            vec![vec![[1u8; 64], [2u8; 64]], vec![[3u8; 64]]],
+            vec![vec![[1u8; 64]], vec![[3u8; 64], [4u8; 64]]],
+        ] {
+            assert!(VerificationCache::new(
+                vec!["spend".into(), "output".into()],
+                &uneven,
+                [9u8; 64]
+            )
+            .is_err());
+        }
+    }
+}
@@ -0,0 +1,671 @@
+//! Ceremony file format.
+//!
+//! A ceremony file is a header describing the circuits it contains, followed
+//! by the `MPCParameters` of each circuit in that order:
+//!
+//! ```text
+//! magic    "MASP-MPC"
+//! version  u32
+//! flags    u32, version 2 only
+//! count    u32
+//! circuits count * circuit entry
+//! params   count * MPCParameters
+//! ```
+//!
+//! A circuit entry is the circuit name (u8 length, utf-8 bytes) followed by an
+//! optional [`Fingerprint`] (u8 presence flag, then constraints, inputs and
+//! aux as u32 and the 32-byte constraint system hash).
+//!
+//! The circuit entries are followed by the file's history: a u32 count and
+//! the BLAKE2b-512 digests of the params files the latest rounds were
+//! contributed to, oldest first, up to [`MAX_HISTORY`] of them. The last
+//! entry is the digest of the file this one was produced from. Nothing
+//! authenticates the history: it can be rewritten without invalidating the
+//! parameters.
+//!
+//! The only flag is bit 0, set if the H, L, A, B_G1 and B_G2 queries are
+//! stored with compressed points (see [`Encoding`]). Files without flags are
+//! written as version 1, which has no flags field.
+//!
+//! Files from before the header was introduced start with 64 zero bytes and
+//! always contain the Spend, Output and Convert circuits.
+
+use crate::circuits::{self, Fingerprint};
+use crate::layout::{self, Encoding, Limits};
+use crate::progress::{self, Mode};
+use blake2::{Blake2b512, Digest};
+use bls12_381::G1Affine;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use masp_phase2::MPCParameters;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+pub const MAGIC: [u8; 8] = *b"MASP-MPC";
+pub const VERSION: u32 = 2;
+
+/// Header flag set for [`Encoding::Compressed`].
+pub const FLAG_COMPRESSED: u32 = 1;
+
+/// Length of the all-zero header of legacy ceremony files.
+pub const LEGACY_HEADER_LEN: usize = 64;
+
+/// Upper bound on the number of circuits accepted when reading a header.
+pub const MAX_CIRCUITS: usize = 64;
+
+/// Upper bound on the number of history entries accepted when reading a
+/// header.
+pub const MAX_HISTORY: usize = 1 << 16;
+
+/// Description of one circuit in a ceremony file header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CircuitEntry {
+    pub name: String,
+    /// Shape of the circuit the parameters were created for, if known.
+    pub fingerprint: Option<Fingerprint>,
+}
+
+/// Header of a ceremony file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    /// The circuits in the file, in order.
+    pub circuits: Vec<CircuitEntry>,
+    /// File digests of the previous rounds, oldest first.
+    pub history: Vec<[u8; 64]>,
+    /// Encoding of the parameters of every circuit.
+    pub encoding: Encoding,
+}
+
+impl Header {
+    pub fn new(circuits: Vec<CircuitEntry>) -> Header {
+        Header {
+            circuits,
+            history: vec![],
+            encoding: Encoding::Uncompressed,
+        }
+    }
+
+    /// Header implied by a legacy file.
+    pub fn legacy() -> Header {
+        Header::new(
+            circuits::MASP_CIRCUITS
+                .iter()
+                .map(|name| CircuitEntry {
+                    name: name.to_string(),
+                    fingerprint: None,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Header> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+
+        if magic == [0u8; 8] {
+            let mut rest = [0u8; LEGACY_HEADER_LEN - 8];
+            reader.read_exact(&mut rest)?;
+            if rest.iter().any(|b| *b != 0) {
+                return Err(invalid_data("unrecognized ceremony file header"));
+            }
+            return Ok(Header::legacy());
+        }
+        if magic != MAGIC {
+            return Err(invalid_data("unrecognized ceremony file header"));
+        }
+
+        let version = reader.read_u32::<BigEndian>()?;
+        let encoding = match version {
+            1 => Encoding::Uncompressed,
+            VERSION => match reader.read_u32::<BigEndian>()? {
+                FLAG_COMPRESSED => Encoding::Compressed,
+                // Files without flags are written as version 1
+                flags => return Err(invalid_data(format!("invalid header flags {:#x}", flags))),
+            },
+            _ => {
+                return Err(invalid_data(format!(
+                    "unsupported ceremony file version {}",
+                    version
+                )))
+            }
+        };
+
+        let count = reader.read_u32::<BigEndian>()? as usize;
+        if count == 0 || count > MAX_CIRCUITS {
+            return Err(invalid_data(format!("invalid circuit count {}", count)));
+        }
+
+        let mut circuits = Vec::with_capacity(count);
+        for _ in 0..count {
+            let len = reader.read_u8()? as usize;
+            let mut name = vec![0u8; len];
+            reader.read_exact(&mut name)?;
+            let name =
+                String::from_utf8(name).map_err(|_| invalid_data("circuit name is not utf-8"))?;
+
+            let fingerprint = match reader.read_u8()? {
+                0 => None,
+                1 => {
+                    let constraints = reader.read_u32::<BigEndian>()?;
+                    let inputs = reader.read_u32::<BigEndian>()?;
+                    let aux = reader.read_u32::<BigEndian>()?;
+                    let mut hash = [0u8; 32];
+                    reader.read_exact(&mut hash)?;
+                    Some(Fingerprint {
+                        constraints,
+                        inputs,
+                        aux,
+                        hash,
+                    })
+                }
+                _ => return Err(invalid_data("invalid fingerprint flag")),
+            };
+
+            circuits.push(CircuitEntry { name, fingerprint });
+        }
+
+        let count = reader.read_u32::<BigEndian>()? as usize;
+        if count > MAX_HISTORY {
+            return Err(invalid_data(format!("invalid history length {}", count)));
+        }
+        let mut history = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut digest = [0u8; 64];
+            reader.read_exact(&mut digest)?;
+            history.push(digest);
+        }
+
+        let header = Header {
+            circuits,
+            history,
+            encoding,
+        };
+        header.validate()?;
+        Ok(header)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.validate()?;
+
+        writer.write_all(&MAGIC)?;
+        match self.encoding {
+            Encoding::Uncompressed => writer.write_u32::<BigEndian>(1)?,
+            Encoding::Compressed => {
+                writer.write_u32::<BigEndian>(VERSION)?;
+                writer.write_u32::<BigEndian>(FLAG_COMPRESSED)?;
+            }
+        }
+        writer.write_u32::<BigEndian>(self.circuits.len() as u32)?;
+        for entry in &self.circuits {
+            writer.write_u8(entry.name.len() as u8)?;
+            writer.write_all(entry.name.as_bytes())?;
+
+            match &entry.fingerprint {
+                None => writer.write_u8(0)?,
+                Some(fingerprint) => {
+                    writer.write_u8(1)?;
+                    writer.write_u32::<BigEndian>(fingerprint.constraints)?;
+                    writer.write_u32::<BigEndian>(fingerprint.inputs)?;
+                    writer.write_u32::<BigEndian>(fingerprint.aux)?;
+                    writer.write_all(&fingerprint.hash)?;
+                }
+            }
+        }
+
+        writer.write_u32::<BigEndian>(self.history.len() as u32)?;
+        for digest in &self.history {
+            writer.write_all(digest)?;
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        if self.history.len() > MAX_HISTORY {
+            return Err(invalid_data(format!(
+                "invalid history length {}",
+                self.history.len()
+            )));
+        }
+        if self.circuits.is_empty() || self.circuits.len() > MAX_CIRCUITS {
+            return Err(invalid_data(format!(
+                "invalid circuit count {}",
+                self.circuits.len()
+            )));
+        }
+        for (i, entry) in self.circuits.iter().enumerate() {
+            let name = &entry.name;
+            if name.is_empty() || name.len() > u8::MAX as usize {
+                return Err(invalid_data(format!("invalid circuit name `{}`", name)));
+            }
+            if self.circuits[..i].iter().any(|other| &other.name == name) {
+                return Err(invalid_data(format!("duplicate circuit `{}`", name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether reading a params file checks that every point is on the curve and
+/// in the prime order subgroup.
+///
+/// Skipping the checks on a file received from someone else is a soundness
+/// risk, so commands that verify always check and the others check unless
+/// given `--skip-point-checks`, meant for files written on the same machine.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PointChecks {
+    Full,
+    /// The file was written locally, or its digest matches a verified file.
+    Skip,
+}
+
+impl PointChecks {
+    pub const SKIP_FLAG: &'static str = "--skip-point-checks";
+
+    /// Removes `--skip-point-checks` from `args` and returns the checks it
+    /// selects.
+    pub fn from_args(args: &mut Vec<String>) -> PointChecks {
+        let mut checks = PointChecks::Full;
+        args.retain(|arg| {
+            if arg == PointChecks::SKIP_FLAG {
+                checks = PointChecks::Skip;
+                false
+            } else {
+                true
+            }
+        });
+        checks
+    }
+
+    pub fn checked(self) -> bool {
+        self == PointChecks::Full
+    }
+
+    /// Reports the checks done when reading `path`.
+    pub fn report(self, mode: Mode, path: &str) {
+        match self {
+            PointChecks::Full => mode.message(path, "checking every point"),
+            PointChecks::Skip => mode.message(
+                path,
+                "skipping point checks, the file must come from a trusted source",
+            ),
+        }
+    }
+}
+
+/// Whether reading a params file accepts circuits that aren't linked into
+/// this binary.
+///
+/// What reading the parameters of a circuit allocates is bounded by the
+/// circuit's shape, taken from the registry in [`circuits`] rather than from
+/// the file's header, which anyone can rewrite. Circuits the registry doesn't
+/// know are only read with `--allow-unknown-circuits`, up to [`Limits::any`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnknownCircuits {
+    Reject,
+    Allow,
+}
+
+impl UnknownCircuits {
+    pub const ALLOW_FLAG: &'static str = "--allow-unknown-circuits";
+
+    /// Removes `--allow-unknown-circuits` from `args` and returns the policy
+    /// it selects.
+    pub fn from_args(args: &mut Vec<String>) -> UnknownCircuits {
+        let mut unknown = UnknownCircuits::Reject;
+        args.retain(|arg| {
+            if arg == UnknownCircuits::ALLOW_FLAG {
+                unknown = UnknownCircuits::Allow;
+                false
+            } else {
+                true
+            }
+        });
+        unknown
+    }
+
+    /// Limits for reading the parameters of the circuit `entry` describes.
+    /// Fails if the header records a fingerprint of another shape than the
+    /// registered circuit's, or if the circuit isn't registered and isn't
+    /// allowed.
+    pub fn limits(self, entry: &CircuitEntry) -> io::Result<Limits> {
+        match circuits::registered_shape(&entry.name) {
+            Some(shape) => {
+                if matches!(&entry.fingerprint, Some(f) if f.shape() != shape) {
+                    return Err(invalid_data(format!(
+                        "{} doesn't match the circuit linked into this binary",
+                        circuits::label(&entry.name)
+                    )));
+                }
+                Ok(Limits::from_shape(&shape))
+            }
+            None if self == UnknownCircuits::Allow => Ok(Limits::any()),
+            None => Err(invalid_data(format!(
+                "circuit `{}` isn't linked into this binary, pass {} to read it anyway",
+                entry.name,
+                UnknownCircuits::ALLOW_FLAG
+            ))),
+        }
+    }
+}
+
+/// Parameters of one circuit of a ceremony.
+pub struct CircuitParams {
+    pub name: String,
+    pub fingerprint: Option<Fingerprint>,
+    pub params: MPCParameters,
+}
+
+impl CircuitParams {
+    /// Number of points a contribution updates: the H and L queries and delta.
+    pub fn contribution_points(&self) -> u64 {
+        let params = self.params.get_params();
+        (params.h.len() + params.l.len() + 2) as u64
+    }
+}
+
+/// The contents of a ceremony file.
+pub struct Ceremony {
+    pub circuits: Vec<CircuitParams>,
+    /// File digests of the previous rounds, see [`Header::history`].
+    pub history: Vec<[u8; 64]>,
+    pub encoding: Encoding,
+}
+
+impl Ceremony {
+    pub fn header(&self) -> Header {
+        Header {
+            circuits: self
+                .circuits
+                .iter()
+                .map(|c| CircuitEntry {
+                    name: c.name.clone(),
+                    fingerprint: c.fingerprint.clone(),
+                })
+                .collect(),
+            history: self.history.clone(),
+            encoding: self.encoding,
+        }
+    }
+
+    pub fn read<R: Read>(
+        mut reader: R,
+        checked: bool,
+        unknown: UnknownCircuits,
+    ) -> io::Result<Ceremony> {
+        let header = Header::read(&mut reader)?;
+
+        let mut circuits = Vec::with_capacity(header.circuits.len());
+        for entry in header.circuits.iter().cloned() {
+            let limits = unknown.limits(&entry)?;
+            let params =
+                layout::read_encoded_params(&mut reader, checked, limits, header.encoding)?;
+            circuits.push(CircuitParams {
+                name: entry.name,
+                fingerprint: entry.fingerprint,
+                params,
+            });
+        }
+
+        Ok(Ceremony {
+            circuits,
+            history: header.history,
+            encoding: header.encoding,
+        })
+    }
+
+    /// Reads the ceremony file at `path`, reporting progress and the point
+    /// checks done.
+    pub fn open<P: AsRef<Path>>(
+        mode: Mode,
+        path: P,
+        checks: PointChecks,
+        unknown: UnknownCircuits,
+    ) -> io::Result<Ceremony> {
+        let path = path.as_ref();
+        checks.report(mode, &path.display().to_string());
+
+        let mut reader = progress::open(mode, path)?;
+        let ceremony = Ceremony::read(&mut reader, checks.checked(), unknown)?;
+        reader.into_inner().finish();
+        Ok(ceremony)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.header().write(&mut writer)?;
+        for circuit in &self.circuits {
+            layout::write_encoded_params(&circuit.params, &mut writer, self.encoding)?;
+        }
+
+        Ok(())
+    }
+
+    /// Length of [`Ceremony::write`]'s output.
+    pub fn serialized_len(&self) -> u64 {
+        let mut header = vec![];
+        self.header()
+            .write(&mut header)
+            .expect("writing to a Vec can't fail");
+
+        let params: u64 = self
+            .circuits
+            .iter()
+            .map(|c| layout::encoded_len(&c.params, self.encoding))
+            .sum();
+        header.len() as u64 + params
+    }
+
+    /// Records `digest` as that of the file this round was contributed to,
+    /// keeping only the latest [`MAX_HISTORY`] rounds.
+    pub fn push_history(&mut self, digest: [u8; 64]) {
+        self.history.push(digest);
+        if self.history.len() > MAX_HISTORY {
+            let excess = self.history.len() - MAX_HISTORY;
+            self.history.drain(..excess);
+        }
+    }
+
+    /// Hashes of the contribution public keys, per circuit.
+    pub fn contributions(&self) -> Vec<Vec<[u8; 64]>> {
+        self.circuits
+            .iter()
+            .map(|c| contribution_hashes(&c.params))
+            .collect()
+    }
+}
+
+/// Hashes the public keys of all contributions to `params`, the same way
+/// `MPCParameters::verify` reports them.
+pub fn contribution_hashes(params: &MPCParameters) -> Vec<[u8; 64]> {
+    params
+        .contributions
+        .iter()
+        .map(|pubkey| {
+            let sink = io::sink();
+            let mut sink = masp_phase2::HashWriter::new(sink);
+            pubkey.write(&mut sink).unwrap();
+            let h = sink.into_hash();
+            let mut response = [0u8; 64];
+            response.copy_from_slice(h.as_ref());
+            response
+        })
+        .collect()
+}
+
+/// Checks that the current delta of `params` is the one produced by its latest
+/// contribution, or the initial delta if there are none. A contribution list
+/// that was truncated, reordered or taken from other parameters fails this.
+pub fn delta_matches_contributions(params: &MPCParameters) -> bool {
+    let delta_g1 = params.get_params().vk.delta_g1.to_uncompressed();
+    match params.contributions.last() {
+        None => delta_g1 == G1Affine::generator().to_uncompressed(),
+        Some(pubkey) => {
+            // The serialized public key starts with the delta it produced
+            let mut bytes = vec![];
+            pubkey.write(&mut bytes).unwrap();
+            bytes[..delta_g1.len()] == delta_g1[..]
+        }
+    }
+}
+
+/// Combines the per-circuit hashes of one contribution into the hash printed
+/// to participants.
+pub fn combined_hash(hashes: &[[u8; 64]]) -> [u8; 64] {
+    let mut h = Blake2b512::new();
+    for hash in hashes {
+        h.update(&hash[..]);
+    }
+    let mut response = [0u8; 64];
+    response.copy_from_slice(h.finalize().as_ref());
+    response
+}
+
+/// Combines per-circuit contribution lists into one hash per round.
+///
+/// Fails if the circuits do not have the same number of contributions.
+pub fn combined_hashes(contributions: &[Vec<[u8; 64]>]) -> io::Result<Vec<[u8; 64]>> {
+    let rounds = contributions.first().map_or(0, |c| c.len());
+    if contributions.iter().any(|c| c.len() != rounds) {
+        return Err(invalid_data(
+            "circuits have different numbers of contributions",
+        ));
+    }
+
+    Ok((0..rounds)
+        .map(|round| {
+            let hashes: Vec<[u8; 64]> = contributions.iter().map(|c| c[round]).collect();
+            combined_hash(&hashes)
+        })
+        .collect())
+}
+
+pub(crate) fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        let header = Header::new(vec![
+            CircuitEntry {
+                name: "spend".into(),
+                fingerprint: Some(Fingerprint {
+                    constraints: 100637,
+                    inputs: 8,
+                    aux: 98777,
+                    hash: [7u8; 32],
+                }),
+            },
+            CircuitEntry {
+                name: "asset-type".into(),
+                fingerprint: None,
+            },
+        ]);
+        let mut buf = vec![];
+        header.write(&mut buf).unwrap();
+        assert_eq!(Header::read(&buf[..]).unwrap(), header);
+
+        let header = Header {
+            history: vec![[1u8; 64], [2u8; 64]],
+            ..header
+        };
+        let mut buf = vec![];
+        header.write(&mut buf).unwrap();
+        assert_eq!(Header::read(&buf[..]).unwrap(), header);
+        assert_eq!(buf[8..12], 1u32.to_be_bytes());
+
+        let header = Header {
+            encoding: Encoding::Compressed,
+            ..header
+        };
+        let mut buf = vec![];
+        header.write(&mut buf).unwrap();
+        assert_eq!(Header::read(&buf[..]).unwrap(), header);
+
+        // Flags are only written when set, and unknown ones are rejected
+        buf[15] = 0;
+        assert!(Header::read(&buf[..]).is_err());
+        buf[15] = 3;
+        assert!(Header::read(&buf[..]).is_err());
+    }
+
+    #[test]
+    fn legacy_header() {
+        let buf = [0u8; LEGACY_HEADER_LEN];
+        assert_eq!(Header::read(&buf[..]).unwrap(), Header::legacy());
+
+        let mut buf = [0u8; LEGACY_HEADER_LEN];
+        buf[10] = 1;
+        assert!(Header::read(&buf[..]).is_err());
+    }
+
+    #[test]
+    fn unknown_circuit_limits() {
+        let unknown = CircuitEntry {
+            name: "asset-type".into(),
+            fingerprint: None,
+        };
+        assert!(UnknownCircuits::Reject.limits(&unknown).is_err());
+        assert_eq!(
+            UnknownCircuits::Allow.limits(&unknown).unwrap(),
+            Limits::any()
+        );
+    }
+
+    #[cfg(feature = "circuits")]
+    #[test]
+    fn registered_circuit_limits() {
+        // The limits of a registered circuit ignore the header, which must
+        // agree with the registry if it records a fingerprint
+        let fingerprint = circuits::registered_fingerprint(circuits::OUTPUT).unwrap();
+        let mut entry = CircuitEntry {
+            name: circuits::OUTPUT.into(),
+            fingerprint: None,
+        };
+        assert_eq!(
+            UnknownCircuits::Reject.limits(&entry).unwrap(),
+            Limits::from_shape(&fingerprint.shape())
+        );
+        entry.fingerprint = Some(Fingerprint {
+            constraints: u32::MAX,
+            ..fingerprint
+        });
+        assert!(UnknownCircuits::Allow.limits(&entry).is_err());
+    }
+
+    #[test]
+    fn history_window() {
+        let mut ceremony = Ceremony {
+            circuits: vec![],
+            history: vec![[0u8; 64]; MAX_HISTORY],
+            encoding: Encoding::Uncompressed,
+        };
+        ceremony.push_history([1u8; 64]);
+        assert_eq!(ceremony.history.len(), MAX_HISTORY);
+        assert_eq!(ceremony.history.last(), Some(&[1u8; 64]));
+    }
+
+    #[test]
+    fn combined_hash_matches_contribute() {
+        let spend = [1u8; 64];
+        let output = [2u8; 64];
+        let convert = [3u8; 64];
+
+        let mut h = Blake2b512::new();
+        h.update(spend);
+        h.update(output);
+        h.update(convert);
+        let expected = h.finalize();
+
+        assert_eq!(
+            &combined_hash(&[spend, output, convert])[..],
+            expected.as_slice()
+        );
+        assert!(combined_hashes(&[vec![spend], vec![]]).is_err());
+    }
+}
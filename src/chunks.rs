@@ -0,0 +1,377 @@
+//! Chunked transfer of params files.
+//!
+//! A params file is split into chunks of a fixed size, the last one possibly
+//! shorter, each named after the file with its index appended. The manifest
+//! lists the file and the BLAKE2b-512 digest of every chunk, so each chunk
+//! can be checked as soon as it is downloaded and a download can resume from
+//! the chunks it already has. Chunks are streamed through a small buffer
+//! rather than held in memory:
+//!
+//! ```text
+//! file <name> <len> <file digest>
+//! chunk_size <bytes>
+//! <index> <chunk digest>
+//! ```
+
+use crate::ceremony::invalid_data;
+use crate::fs::{AtomicFile, Summary};
+use crate::progress::{self, Mode};
+use crate::{into_hex, parse_hash};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+/// Default chunk size, 16 MiB.
+pub const DEFAULT_CHUNK_SIZE: u64 = 1 << 24;
+
+/// Upper bound on the number of chunks accepted when reading a manifest.
+pub const MAX_CHUNKS: u64 = 1 << 16;
+
+/// Upper bound on the chunk size, 256 MiB, so a manifest can't make a
+/// single chunk too large to download in one go.
+pub const MAX_CHUNK_SIZE: u64 = 1 << 28;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest {
+    /// File name of the params file, without any directory.
+    pub name: String,
+    pub len: u64,
+    pub digest: [u8; 64],
+    pub chunk_size: u64,
+    /// Digest of each chunk, in order.
+    pub chunks: Vec<[u8; 64]>,
+}
+
+impl Manifest {
+    /// Name of the manifest of the params file `name`.
+    pub fn file_name(name: &str) -> String {
+        format!("{}.chunks", name)
+    }
+
+    pub fn chunk_name(&self, i: usize) -> String {
+        format!("{}.{:05}", self.name, i)
+    }
+
+    /// Offset of chunk `i` in the params file.
+    pub fn chunk_offset(&self, i: usize) -> u64 {
+        i as u64 * self.chunk_size
+    }
+
+    pub fn chunk_len(&self, i: usize) -> u64 {
+        self.chunk_size.min(self.len - self.chunk_offset(i))
+    }
+
+    /// Whether `dir` holds chunk `i`, with the right contents.
+    pub fn has_chunk<P: AsRef<Path>>(&self, dir: P, i: usize) -> io::Result<bool> {
+        match self.check_chunk(i, dir.as_ref().join(self.chunk_name(i))) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            result => result,
+        }
+    }
+
+    /// Whether the file at `path` has the length and digest of chunk `i`.
+    pub fn check_chunk<P: AsRef<Path>>(&self, i: usize, path: P) -> io::Result<bool> {
+        self.copy_chunk(i, File::open(path)?, io::sink())
+    }
+
+    /// Indices of the chunks missing from `dir`, or not matching the
+    /// manifest.
+    pub fn missing_chunks<P: AsRef<Path>>(&self, dir: P) -> io::Result<Vec<usize>> {
+        let mut missing = vec![];
+        for i in 0..self.chunks.len() {
+            if !self.has_chunk(&dir, i)? {
+                missing.push(i);
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Copies `reader`, which should hold chunk `i`, to `writer` and returns
+    /// whether it had the chunk's length and digest. Reads at most one byte
+    /// past the chunk's length.
+    fn copy_chunk<R: Read, W: Write>(
+        &self,
+        i: usize,
+        reader: R,
+        mut writer: W,
+    ) -> io::Result<bool> {
+        let len = self.chunk_len(i);
+        let mut reader = DigestReader::new(reader.take(len + 1));
+        let copied = io::copy(&mut reader, &mut writer)?;
+        Ok(copied == len && reader.digest() == self.chunks[i])
+    }
+
+    pub fn read<R: Read>(reader: R) -> io::Result<Manifest> {
+        let malformed = || invalid_data("malformed chunk manifest");
+        let mut lines = BufReader::new(reader).lines();
+        let mut next_line = || -> io::Result<String> { lines.next().ok_or_else(malformed)? };
+
+        let line = next_line()?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.len() != 4 || words[0] != "file" {
+            return Err(malformed());
+        }
+        let name = words[1].to_string();
+        let len: u64 = words[2].parse().map_err(|_| malformed())?;
+        let digest = parse_hash(words[3]).ok_or_else(|| invalid_data("malformed file digest"))?;
+
+        let line = next_line()?;
+        let chunk_size: u64 = match line.strip_prefix("chunk_size ") {
+            Some(size) => size.trim().parse().map_err(|_| malformed())?,
+            None => return Err(malformed()),
+        };
+        let count = len.div_ceil(chunk_size.max(1));
+        if count > MAX_CHUNKS {
+            return Err(invalid_data(format!("too many chunks: {}", count)));
+        }
+
+        let mut chunks = vec![];
+        for i in 0..count {
+            let line = next_line()?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.len() != 2 || words[0] != i.to_string() {
+                return Err(malformed());
+            }
+            chunks
+                .push(parse_hash(words[1]).ok_or_else(|| invalid_data("malformed chunk digest"))?);
+        }
+        if lines.next().is_some() {
+            return Err(malformed());
+        }
+
+        let manifest = Manifest {
+            name,
+            len,
+            digest,
+            chunk_size,
+            chunks,
+        };
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.validate()?;
+
+        writeln!(
+            writer,
+            "file {} {} {}",
+            self.name,
+            self.len,
+            into_hex(&self.digest)
+        )?;
+        writeln!(writer, "chunk_size {}", self.chunk_size)?;
+        for (i, digest) in self.chunks.iter().enumerate() {
+            writeln!(writer, "{} {}", i, into_hex(digest))?;
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        // Chunks are written next to the manifest under this name
+        let name = &self.name;
+        if name.is_empty()
+            || name == "."
+            || name == ".."
+            || name.contains(|c: char| c == '/' || c == '\\' || c.is_whitespace())
+        {
+            return Err(invalid_data(format!("invalid file name `{}`", name)));
+        }
+        if self.len == 0 {
+            return Err(invalid_data("empty params file"));
+        }
+        if self.chunk_size == 0 || self.chunk_size > MAX_CHUNK_SIZE {
+            return Err(invalid_data(format!(
+                "invalid chunk size {}, must be between 1 and {}",
+                self.chunk_size, MAX_CHUNK_SIZE
+            )));
+        }
+        if self.chunks.len() as u64 != self.len.div_ceil(self.chunk_size) {
+            return Err(invalid_data("chunk count doesn't match the file length"));
+        }
+        Ok(())
+    }
+}
+
+/// Splits the params file at `path` into chunks of `chunk_size` bytes in
+/// `out_dir`, and writes their manifest there too. Existing files are only
+/// replaced with `overwrite`.
+pub fn split<P: AsRef<Path>, Q: AsRef<Path>>(
+    mode: Mode,
+    path: P,
+    out_dir: Q,
+    chunk_size: u64,
+    overwrite: bool,
+) -> io::Result<Manifest> {
+    let path = path.as_ref();
+    let out_dir = out_dir.as_ref();
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?
+        .to_string();
+    let len = fs::metadata(path)?.len();
+
+    let count = len.div_ceil(chunk_size.max(1)) as usize;
+    let mut manifest = Manifest {
+        name,
+        len,
+        digest: [0; 64],
+        chunk_size,
+        chunks: vec![[0; 64]; count],
+    };
+    // Checks the name and sizes before writing anything
+    manifest.validate()?;
+
+    let mut reader = DigestReader::new(progress::open(mode, path)?);
+    for i in 0..count {
+        let mut f = AtomicFile::create(out_dir.join(manifest.chunk_name(i)), overwrite)?;
+        let len = manifest.chunk_len(i);
+        if io::copy(&mut (&mut reader).take(len), &mut f)? != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} changed while splitting it", path.display()),
+            ));
+        }
+        manifest.chunks[i] = f.commit()?.hash;
+    }
+    manifest.digest = reader.digest();
+    reader.inner.into_inner().finish();
+
+    let mut f = AtomicFile::create(out_dir.join(Manifest::file_name(&manifest.name)), overwrite)?;
+    manifest.write(&mut f)?;
+    f.commit()?;
+
+    Ok(manifest)
+}
+
+/// Joins the chunks listed in `manifest` from `chunk_dir` into `out_path`,
+/// checking each of them and the digest of the whole file.
+pub fn assemble<P: AsRef<Path>, Q: AsRef<Path>>(
+    mode: Mode,
+    manifest: &Manifest,
+    chunk_dir: P,
+    out_path: Q,
+    overwrite: bool,
+) -> io::Result<Summary> {
+    let chunk_dir = chunk_dir.as_ref();
+    let out_path = out_path.as_ref();
+
+    let mut f = AtomicFile::create(out_path, overwrite)?;
+    let mut writer = progress::writer(mode, out_path, manifest.len, &mut f);
+    for i in 0..manifest.chunks.len() {
+        let path = chunk_dir.join(manifest.chunk_name(i));
+        // A bad chunk discards the file, so it's never moved into place
+        if !manifest.copy_chunk(i, File::open(&path)?, &mut writer)? {
+            return Err(invalid_data(format!(
+                "{} doesn't match the manifest",
+                path.display()
+            )));
+        }
+    }
+    writer.finish();
+
+    // Checked before committing, so a bad manifest never replaces anything
+    if f.digest() != manifest.digest {
+        return Err(invalid_data("assembled file doesn't match the manifest"));
+    }
+
+    f.commit()
+}
+
+/// Reader computing the BLAKE2b-512 digest of what is read through it.
+struct DigestReader<R> {
+    inner: R,
+    state: blake2b_simd::State,
+}
+
+impl<R> DigestReader<R> {
+    fn new(inner: R) -> DigestReader<R> {
+        DigestReader {
+            inner,
+            state: blake2b_simd::State::new(),
+        }
+    }
+
+    fn digest(&self) -> [u8; 64] {
+        let mut digest = [0u8; 64];
+        digest.copy_from_slice(self.state.finalize().as_bytes());
+        digest
+    }
+}
+
+impl<R: Read> Read for DigestReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.state.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_and_assemble() {
+        let dir = std::env::temp_dir().join(format!("masp-mpc-chunks-{}", std::process::id()));
+        let chunk_dir = dir.join("chunks");
+        fs::create_dir_all(&chunk_dir).unwrap();
+        let path = dir.join("round1.params");
+        let contents: Vec<u8> = (0..2500u32).map(|i| (i * 7) as u8).collect();
+        fs::write(&path, &contents).unwrap();
+
+        let manifest = split(Mode::Quiet, &path, &chunk_dir, 1000, false).unwrap();
+        assert_eq!(manifest.chunks.len(), 3);
+        assert_eq!(manifest.chunk_len(2), 500);
+        assert_eq!(
+            &manifest.digest[..],
+            blake2b_simd::blake2b(&contents).as_bytes()
+        );
+        let mut written = vec![];
+        manifest.write(&mut written).unwrap();
+        assert_eq!(Manifest::read(&written[..]).unwrap(), manifest);
+        assert_eq!(
+            fs::read(chunk_dir.join("round1.params.chunks")).unwrap(),
+            written
+        );
+        assert!(split(Mode::Quiet, &path, &chunk_dir, 1000, false).is_err());
+
+        let out = dir.join("out.params");
+        let summary = assemble(Mode::Quiet, &manifest, &chunk_dir, &out, false).unwrap();
+        assert_eq!(summary.hash, manifest.digest);
+        assert_eq!(fs::read(&out).unwrap(), contents);
+
+        // A wrong file digest is caught before the output is replaced
+        let bad_digest = Manifest {
+            digest: [0; 64],
+            ..manifest.clone()
+        };
+        assert!(assemble(Mode::Quiet, &bad_digest, &chunk_dir, &out, true).is_err());
+        assert_eq!(fs::read(&out).unwrap(), contents);
+
+        // A damaged chunk is caught before anything is written
+        fs::remove_file(&out).unwrap();
+        fs::write(chunk_dir.join(manifest.chunk_name(1)), &contents[..1000]).unwrap();
+        assert_eq!(manifest.missing_chunks(&chunk_dir).unwrap(), vec![1]);
+        assert!(assemble(Mode::Quiet, &manifest, &chunk_dir, &out, false).is_err());
+        assert!(!out.exists());
+
+        let bad_name = Manifest {
+            name: "../round1.params".into(),
+            ..manifest
+        };
+        assert!(bad_name.write(io::sink()).is_err());
+        let huge_chunks = Manifest {
+            name: "round1.params".into(),
+            len: MAX_CHUNK_SIZE + 1,
+            chunk_size: MAX_CHUNK_SIZE + 1,
+            chunks: vec![[0; 64]],
+            ..bad_name
+        };
+        assert!(huge_chunks.write(io::sink()).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
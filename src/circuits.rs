@@ -0,0 +1,419 @@
+//! Registry of the circuits that can take part in a ceremony.
+//!
+//! Ceremony files refer to circuits by name; this module maps those names to
+//! the circuits linked from `masp_proofs`. Supporting a new circuit means
+//! adding a name here and a variant to [`MaspCircuit`]. Without the
+//! `circuits` feature no circuit is linked, and every circuit is unknown.
+
+#[cfg(feature = "circuits")]
+use std::collections::BTreeMap;
+#[cfg(feature = "circuits")]
+use std::sync::{Mutex, OnceLock};
+
+pub const SPEND: &str = "spend";
+pub const OUTPUT: &str = "output";
+pub const CONVERT: &str = "convert";
+
+/// Circuits of the MASP ceremony, in the order they appear in the file.
+pub const MASP_CIRCUITS: &[&str] = &[SPEND, OUTPUT, CONVERT];
+
+/// Human readable name of a circuit, used in log messages.
+pub fn label(name: &str) -> String {
+    match name {
+        SPEND => "MASP Spend".to_string(),
+        OUTPUT => "MASP Output".to_string(),
+        CONVERT => "MASP Convert".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Shape of a circuit's constraint system, used to check that two binaries
+/// were built against the same circuits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fingerprint {
+    pub constraints: u32,
+    /// Number of public inputs, including the constant one.
+    pub inputs: u32,
+    pub aux: u32,
+    /// `TestConstraintSystem::hash` of the circuit.
+    pub hash: [u8; 32],
+}
+
+impl Fingerprint {
+    pub fn shape(&self) -> Shape {
+        Shape {
+            constraints: self.constraints,
+            inputs: self.inputs,
+            aux: self.aux,
+        }
+    }
+}
+
+/// Sizes of a circuit's constraint system, which are all that bound its
+/// parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shape {
+    pub constraints: u32,
+    /// Number of public inputs, including the constant one.
+    pub inputs: u32,
+    pub aux: u32,
+}
+
+#[cfg(feature = "circuits")]
+pub use self::registry::MaspCircuit;
+
+/// Shape of the circuit registered under `name`, or `None` if no such
+/// circuit is linked into this binary. Every registered circuit is counted
+/// once per process, without building its constraint system.
+#[cfg(feature = "circuits")]
+pub fn registered_shape(name: &str) -> Option<Shape> {
+    static SHAPES: OnceLock<Vec<(&str, Shape)>> = OnceLock::new();
+
+    SHAPES
+        .get_or_init(|| {
+            MASP_CIRCUITS
+                .iter()
+                .filter_map(|&name| Some((name, MaspCircuit::shape(name)?)))
+                .collect()
+        })
+        .iter()
+        .find(|(registered, _)| *registered == name)
+        .map(|(_, shape)| *shape)
+}
+
+#[cfg(not(feature = "circuits"))]
+pub fn registered_shape(_name: &str) -> Option<Shape> {
+    None
+}
+
+/// Fingerprint of the circuit registered under `name`, or `None` if no such
+/// circuit is linked into this binary. Synthesizing a circuit takes a while,
+/// so each fingerprint is only computed once. Reading parameters only needs
+/// [`registered_shape`].
+#[cfg(feature = "circuits")]
+pub fn registered_fingerprint(name: &str) -> Option<Fingerprint> {
+    static FINGERPRINTS: Mutex<BTreeMap<String, Fingerprint>> = Mutex::new(BTreeMap::new());
+
+    MaspCircuit::blank(name)?;
+    let mut fingerprints = FINGERPRINTS.lock().unwrap();
+    if !fingerprints.contains_key(name) {
+        fingerprints.insert(name.to_string(), MaspCircuit::fingerprint(name)?);
+    }
+    fingerprints.get(name).cloned()
+}
+
+#[cfg(not(feature = "circuits"))]
+pub fn registered_fingerprint(_name: &str) -> Option<Fingerprint> {
+    None
+}
+
+#[cfg(feature = "circuits")]
+mod registry {
+    use super::{Fingerprint, Shape};
+    use bellman::gadgets::test::TestConstraintSystem;
+    use bellman::{Circuit, ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+    use bls12_381::Scalar;
+    use masp_proofs::circuit::convert::Convert;
+    use masp_proofs::circuit::sapling::{Output, Spend};
+    use std::convert::TryInto;
+
+    // Only random witnesses need the note and key types
+    #[cfg(feature = "verification")]
+    use bellman::gadgets::multipack;
+    #[cfg(feature = "verification")]
+    use ff::Field;
+    #[cfg(feature = "verification")]
+    use group::Group;
+    #[cfg(feature = "verification")]
+    use masp_primitives::asset_type::AssetType;
+    #[cfg(feature = "verification")]
+    use masp_primitives::sapling::{Diversifier, PaymentAddress, ProofGenerationKey};
+    #[cfg(feature = "verification")]
+    use rand::{Rng, RngCore};
+
+    /// One of the circuits known to this binary.
+    #[allow(clippy::large_enum_variant)]
+    pub enum MaspCircuit {
+        Spend(Spend),
+        Output(Output),
+        Convert(Convert),
+    }
+
+    impl MaspCircuit {
+        /// Returns the circuit registered under `name` without any witness,
+        /// as needed to create or verify parameters.
+        pub fn blank(name: &str) -> Option<MaspCircuit> {
+            match name {
+                super::SPEND => Some(MaspCircuit::Spend(Spend {
+                    value_commitment: None,
+                    proof_generation_key: None,
+                    payment_address: None,
+                    commitment_randomness: None,
+                    ar: None,
+                    auth_path: vec![None; 32], // Tree depth is 32 for sapling
+                    anchor: None,
+                })),
+                super::OUTPUT => Some(MaspCircuit::Output(Output {
+                    value_commitment: None,
+                    payment_address: None,
+                    commitment_randomness: None,
+                    esk: None,
+                    asset_identifier: vec![None; 256],
+                })),
+                super::CONVERT => Some(MaspCircuit::Convert(Convert {
+                    value_commitment: None,
+                    auth_path: vec![None; 32], // Tree depth is 32 for sapling
+                    anchor: None,
+                })),
+                _ => None,
+            }
+        }
+
+        /// Returns the circuit registered under `name` with a random witness
+        /// satisfying it. Spends and conversions are of value zero, the only
+        /// value for which a random Merkle path needs no matching anchor.
+        #[cfg(feature = "verification")]
+        pub fn random<R: RngCore>(name: &str, rng: &mut R) -> Option<MaspCircuit> {
+            let asset_type = random_asset_type(rng);
+            let value_commitment = |value, rng: &mut R| {
+                Some(asset_type.value_commitment(value, jubjub::Fr::random(rng)))
+            };
+            let auth_path = |rng: &mut R| {
+                (0..32)
+                    .map(|_| Some((Scalar::random(&mut *rng), rng.gen::<bool>())))
+                    .collect()
+            };
+
+            match name {
+                super::SPEND => {
+                    let proof_generation_key = random_key(rng);
+                    Some(MaspCircuit::Spend(Spend {
+                        value_commitment: value_commitment(0, rng),
+                        payment_address: Some(random_address(&proof_generation_key, rng)),
+                        proof_generation_key: Some(proof_generation_key),
+                        commitment_randomness: Some(jubjub::Fr::random(&mut *rng)),
+                        ar: Some(jubjub::Fr::random(&mut *rng)),
+                        auth_path: auth_path(rng),
+                        anchor: Some(Scalar::random(&mut *rng)),
+                    }))
+                }
+                super::OUTPUT => {
+                    let value = rng.next_u64();
+                    Some(MaspCircuit::Output(Output {
+                        value_commitment: value_commitment(value, rng),
+                        payment_address: Some(random_address(&random_key(rng), rng)),
+                        commitment_randomness: Some(jubjub::Fr::random(&mut *rng)),
+                        esk: Some(jubjub::Fr::random(&mut *rng)),
+                        asset_identifier: multipack::bytes_to_bits_le(asset_type.get_identifier())
+                            .into_iter()
+                            .map(Some)
+                            .collect(),
+                    }))
+                }
+                super::CONVERT => Some(MaspCircuit::Convert(Convert {
+                    value_commitment: value_commitment(0, rng),
+                    auth_path: auth_path(rng),
+                    anchor: Some(Scalar::random(&mut *rng)),
+                })),
+                _ => None,
+            }
+        }
+
+        /// Synthesizes the circuit and returns the values of its public
+        /// inputs, without the constant one, as expected by `verify_proof`.
+        pub fn public_inputs(self) -> Result<Vec<Scalar>, SynthesisError> {
+            let mut counter = Counter {
+                witness: true,
+                ..Counter::default()
+            };
+            self.synthesize(&mut counter)?;
+            Ok(counter.values)
+        }
+
+        /// Synthesizes the circuit, only counting its constraints and
+        /// variables.
+        pub fn shape(name: &str) -> Option<Shape> {
+            let mut counter = Counter::default();
+            MaspCircuit::blank(name)?
+                .synthesize(&mut counter)
+                .expect("blank circuit failed to synthesize");
+
+            Some(Shape {
+                constraints: counter.constraints as u32,
+                // Counted like `TestConstraintSystem`, with the constant one
+                inputs: counter.inputs as u32 + 1,
+                aux: counter.aux as u32,
+            })
+        }
+
+        /// Synthesizes the circuit and summarizes its constraint system.
+        pub fn fingerprint(name: &str) -> Option<Fingerprint> {
+            let mut cs = TestConstraintSystem::<Scalar>::new();
+            MaspCircuit::blank(name)?
+                .synthesize(&mut cs)
+                .expect("blank circuit failed to synthesize");
+
+            let shape = MaspCircuit::shape(name)?;
+
+            Some(Fingerprint {
+                constraints: cs.num_constraints() as u32,
+                inputs: cs.num_inputs() as u32,
+                aux: shape.aux,
+                hash: hex::decode(cs.hash())
+                    .expect("constraint system hash is hex")
+                    .try_into()
+                    .expect("constraint system hash is 32 bytes"),
+            })
+        }
+    }
+
+    impl Circuit<Scalar> for MaspCircuit {
+        fn synthesize<CS: ConstraintSystem<Scalar>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            match self {
+                MaspCircuit::Spend(c) => c.synthesize(cs),
+                MaspCircuit::Output(c) => c.synthesize(cs),
+                MaspCircuit::Convert(c) => c.synthesize(cs),
+            }
+        }
+    }
+
+    #[cfg(feature = "verification")]
+    fn random_asset_type<R: RngCore>(rng: &mut R) -> AssetType {
+        loop {
+            let mut name = [0u8; 32];
+            rng.fill_bytes(&mut name);
+            // Not every name hashes to a valid asset generator
+            if let Ok(asset_type) = AssetType::new(&name) {
+                return asset_type;
+            }
+        }
+    }
+
+    #[cfg(feature = "verification")]
+    fn random_key<R: RngCore>(rng: &mut R) -> ProofGenerationKey {
+        ProofGenerationKey {
+            ak: jubjub::SubgroupPoint::random(&mut *rng),
+            nsk: jubjub::Fr::random(&mut *rng),
+        }
+    }
+
+    #[cfg(feature = "verification")]
+    fn random_address<R: RngCore>(key: &ProofGenerationKey, rng: &mut R) -> PaymentAddress {
+        let viewing_key = key.to_viewing_key();
+        loop {
+            let mut diversifier = [0u8; 11];
+            rng.fill_bytes(&mut diversifier);
+            // About half of all diversifiers are valid
+            if let Some(address) = viewing_key.to_payment_address(Diversifier(diversifier)) {
+                return address;
+            }
+        }
+    }
+
+    /// Constraint system that only counts constraints and variables. With
+    /// `witness` it also
+    /// evaluates them and records the values of the public inputs; gadgets
+    /// learn the values of the variables they allocate from that evaluation,
+    /// so auxiliary variables must be evaluated too.
+    #[derive(Default)]
+    struct Counter {
+        witness: bool,
+        constraints: usize,
+        aux: usize,
+        inputs: usize,
+        values: Vec<Scalar>,
+    }
+
+    impl ConstraintSystem<Scalar> for Counter {
+        type Root = Self;
+
+        fn alloc<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+        where
+            F: FnOnce() -> Result<Scalar, SynthesisError>,
+            A: FnOnce() -> AR,
+            AR: Into<String>,
+        {
+            if self.witness {
+                f()?;
+            }
+            self.aux += 1;
+            Ok(Variable::new_unchecked(Index::Aux(self.aux - 1)))
+        }
+
+        fn alloc_input<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+        where
+            F: FnOnce() -> Result<Scalar, SynthesisError>,
+            A: FnOnce() -> AR,
+            AR: Into<String>,
+        {
+            if self.witness {
+                self.values.push(f()?);
+            }
+            self.inputs += 1;
+            Ok(Variable::new_unchecked(Index::Input(self.inputs)))
+        }
+
+        fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, _: LA, _: LB, _: LC)
+        where
+            A: FnOnce() -> AR,
+            AR: Into<String>,
+            LA: FnOnce(LinearCombination<Scalar>) -> LinearCombination<Scalar>,
+            LB: FnOnce(LinearCombination<Scalar>) -> LinearCombination<Scalar>,
+            LC: FnOnce(LinearCombination<Scalar>) -> LinearCombination<Scalar>,
+        {
+            self.constraints += 1;
+        }
+
+        fn push_namespace<NR, N>(&mut self, _: N)
+        where
+            NR: Into<String>,
+            N: FnOnce() -> NR,
+        {
+        }
+
+        fn pop_namespace(&mut self) {}
+
+        fn get_root(&mut self) -> &mut Self::Root {
+            self
+        }
+    }
+
+    #[cfg(all(test, feature = "verification"))]
+    mod tests {
+        use super::*;
+        use rand::SeedableRng;
+        use rand_chacha::ChaChaRng;
+
+        #[test]
+        fn public_inputs() {
+            let mut rng = ChaChaRng::from_seed([0u8; 32]);
+            for name in crate::circuits::MASP_CIRCUITS {
+                let mut counter = Counter::default();
+                MaspCircuit::blank(name)
+                    .unwrap()
+                    .synthesize(&mut counter)
+                    .unwrap();
+
+                let inputs = MaspCircuit::random(name, &mut rng)
+                    .unwrap()
+                    .public_inputs()
+                    .unwrap();
+                assert_eq!(inputs.len(), counter.inputs);
+            }
+        }
+
+        #[test]
+        fn shapes_match_fingerprints() {
+            for name in crate::circuits::MASP_CIRCUITS {
+                let fingerprint = MaspCircuit::fingerprint(name).unwrap();
+                assert_eq!(
+                    crate::circuits::registered_shape(name),
+                    Some(fingerprint.shape())
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! Contribution deltas.
+//!
+//! A contribution only changes delta, the H and L queries and appends a
+//! public key; IC, A and B are the same as in the file contributed to. A
+//! delta file holds just what changed, about half of a params file, and the
+//! coordinator applies it to the file it was contributed to:
+//!
+//! ```text
+//! magic    "MASP-DLT"
+//! version  u32
+//! flags    u32, as in the ceremony file header
+//! base     64-byte digest of the params file contributed to
+//! count    u32
+//! circuits count * circuit delta
+//! ```
+//!
+//! A circuit delta is the new delta_g1 and delta_g2, the new H and L queries
+//! (length-prefixed, in the encoding the flags give) and the new public key,
+//! serialized as in the params file. The circuits are in the order of the
+//! file contributed to.
+
+use crate::ceremony::{
+    invalid_data, Ceremony, CircuitEntry, CircuitParams, UnknownCircuits, FLAG_COMPRESSED,
+};
+use crate::layout::{self, Encoding, Layout, DELTA_G1, PUBKEY_LEN, VK_FIXED_LEN};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Cursor, Read, Write};
+
+pub const MAGIC: [u8; 8] = *b"MASP-DLT";
+pub const VERSION: u32 = 1;
+
+/// What one contribution changed in the parameters of a circuit, serialized
+/// as in the params file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CircuitDelta {
+    /// delta_g1 and delta_g2.
+    pub delta: Vec<u8>,
+    /// The H query, with its length prefix.
+    pub h: Vec<u8>,
+    /// The L query, with its length prefix.
+    pub l: Vec<u8>,
+    pub pubkey: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delta {
+    /// Digest of the params file contributed to.
+    pub base: [u8; 64],
+    pub encoding: Encoding,
+    pub circuits: Vec<CircuitDelta>,
+}
+
+impl Delta {
+    /// Extracts what the latest contribution changed in each circuit of
+    /// `ceremony`, whose history ends with the file contributed to.
+    pub fn from_ceremony(ceremony: &Ceremony) -> io::Result<Delta> {
+        let base = *ceremony
+            .history
+            .last()
+            .ok_or_else(|| invalid_data("the params have no history"))?;
+
+        let mut circuits = Vec::with_capacity(ceremony.circuits.len());
+        for circuit in &ceremony.circuits {
+            if circuit.params.contributions.is_empty() {
+                return Err(invalid_data(format!("no contribution to {}", circuit.name)));
+            }
+
+            let (bytes, layout) = serialize(circuit, ceremony.encoding)?;
+            let query = |query: &layout::Query| {
+                bytes[query.offset as usize - 4..query.end() as usize].to_vec()
+            };
+            let end = layout.end() as usize;
+            circuits.push(CircuitDelta {
+                delta: bytes[DELTA_G1 as usize..VK_FIXED_LEN as usize].to_vec(),
+                h: query(&layout.h),
+                l: query(&layout.l),
+                pubkey: bytes[end - PUBKEY_LEN..end].to_vec(),
+            });
+        }
+
+        Ok(Delta {
+            base,
+            encoding: ceremony.encoding,
+            circuits,
+        })
+    }
+
+    /// Reads a delta to `base`, failing if its queries don't have the lengths
+    /// of those of `base`.
+    pub fn read<R: Read>(mut reader: R, base: &Ceremony) -> io::Result<Delta> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid_data("unrecognized delta file header"));
+        }
+        let version = reader.read_u32::<BigEndian>()?;
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported delta file version {}",
+                version
+            )));
+        }
+        let encoding = match reader.read_u32::<BigEndian>()? {
+            0 => Encoding::Uncompressed,
+            FLAG_COMPRESSED => Encoding::Compressed,
+            flags => return Err(invalid_data(format!("invalid delta flags {:#x}", flags))),
+        };
+
+        let mut digest = [0u8; 64];
+        reader.read_exact(&mut digest)?;
+        let count = reader.read_u32::<BigEndian>()? as usize;
+        if count != base.circuits.len() {
+            return Err(invalid_data(format!(
+                "delta has {} circuits, the params {}",
+                count,
+                base.circuits.len()
+            )));
+        }
+
+        let mut circuits = Vec::with_capacity(count);
+        for circuit in &base.circuits {
+            let params = circuit.params.get_params();
+            let mut delta = vec![0u8; (VK_FIXED_LEN - DELTA_G1) as usize];
+            reader.read_exact(&mut delta)?;
+            let h = read_query(&mut reader, &circuit.name, "H", params.h.len(), encoding)?;
+            let l = read_query(&mut reader, &circuit.name, "L", params.l.len(), encoding)?;
+            let mut pubkey = vec![0u8; PUBKEY_LEN];
+            reader.read_exact(&mut pubkey)?;
+
+            circuits.push(CircuitDelta {
+                delta,
+                h,
+                l,
+                pubkey,
+            });
+        }
+
+        Ok(Delta {
+            base: digest,
+            encoding,
+            circuits,
+        })
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_u32::<BigEndian>(VERSION)?;
+        writer.write_u32::<BigEndian>(match self.encoding {
+            Encoding::Uncompressed => 0,
+            Encoding::Compressed => FLAG_COMPRESSED,
+        })?;
+        writer.write_all(&self.base)?;
+        writer.write_u32::<BigEndian>(self.circuits.len() as u32)?;
+        for circuit in &self.circuits {
+            writer.write_all(&circuit.delta)?;
+            writer.write_all(&circuit.h)?;
+            writer.write_all(&circuit.l)?;
+            writer.write_all(&circuit.pubkey)?;
+        }
+
+        Ok(())
+    }
+
+    /// Length of [`Delta::write`]'s output.
+    pub fn serialized_len(&self) -> u64 {
+        let circuits: usize = self
+            .circuits
+            .iter()
+            .map(|c| c.delta.len() + c.h.len() + c.l.len() + c.pubkey.len())
+            .sum();
+        (MAGIC.len() + 4 + 4 + 64 + 4 + circuits) as u64
+    }
+
+    /// Rebuilds the ceremony the contributor wrote from `base`, the params
+    /// file with digest [`Delta::base`], checking every point of the result.
+    ///
+    /// This doesn't check that the contribution is valid, use
+    /// `verify_contribution` for that.
+    pub fn apply(&self, base: &Ceremony, unknown: UnknownCircuits) -> io::Result<Ceremony> {
+        if self.circuits.len() != base.circuits.len() {
+            return Err(invalid_data("delta and params have different circuits"));
+        }
+
+        let mut circuits = Vec::with_capacity(base.circuits.len());
+        for (circuit, delta) in base.circuits.iter().zip(&self.circuits) {
+            let (bytes, layout) = serialize(circuit, self.encoding)?;
+            let start = |query: &layout::Query| query.offset as usize - 4;
+
+            let mut spliced = Vec::with_capacity(bytes.len() + PUBKEY_LEN);
+            spliced.extend_from_slice(&bytes[..DELTA_G1 as usize]);
+            spliced.extend_from_slice(&delta.delta);
+            spliced.extend_from_slice(&bytes[VK_FIXED_LEN as usize..start(&layout.h)]);
+            spliced.extend_from_slice(&delta.h);
+            spliced.extend_from_slice(&delta.l);
+            spliced.extend_from_slice(&bytes[start(&layout.a)..start(&layout.contributions)]);
+            spliced.write_u32::<BigEndian>(layout.contributions.len as u32 + 1)?;
+            spliced.extend_from_slice(&bytes[layout.contributions.offset as usize..]);
+            spliced.extend_from_slice(&delta.pubkey);
+
+            let limits = unknown.limits(&CircuitEntry {
+                name: circuit.name.clone(),
+                fingerprint: circuit.fingerprint.clone(),
+            })?;
+            let params = layout::read_encoded_params(&spliced[..], true, limits, self.encoding)?;
+            circuits.push(CircuitParams {
+                name: circuit.name.clone(),
+                fingerprint: circuit.fingerprint.clone(),
+                params,
+            });
+        }
+
+        let mut ceremony = Ceremony {
+            circuits,
+            history: base.history.clone(),
+            encoding: self.encoding,
+        };
+        ceremony.push_history(self.base);
+        Ok(ceremony)
+    }
+}
+
+/// Serializes the parameters of `circuit` in `encoding` and locates their
+/// sections.
+fn serialize(circuit: &CircuitParams, encoding: Encoding) -> io::Result<(Vec<u8>, Layout)> {
+    let mut bytes = Vec::with_capacity(layout::encoded_len(&circuit.params, encoding) as usize);
+    layout::write_encoded_params(&circuit.params, &mut bytes, encoding)?;
+    let layout = Layout::scan_encoded(Cursor::new(&bytes), encoding)?;
+    Ok((bytes, layout))
+}
+
+/// Reads a length-prefixed query of `len` G1 points.
+fn read_query<R: Read>(
+    mut reader: R,
+    circuit: &str,
+    name: &str,
+    len: usize,
+    encoding: Encoding,
+) -> io::Result<Vec<u8>> {
+    let prefix = reader.read_u32::<BigEndian>()?;
+    if prefix as usize != len {
+        return Err(invalid_data(format!(
+            "{} {} query has {} points, the params {}",
+            circuit, name, prefix, len
+        )));
+    }
+
+    let mut query = prefix.to_be_bytes().to_vec();
+    query.resize(4 + len * encoding.g1_len(), 0);
+    reader.read_exact(&mut query[4..])?;
+    Ok(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestCircuit;
+    use masp_phase2::{verify_contribution, MPCParameters};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    #[test]
+    fn apply_matches_contribution() {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+        for encoding in &[Encoding::Uncompressed, Encoding::Compressed] {
+            let mut base = Ceremony {
+                circuits: vec![],
+                history: vec![[1u8; 64]],
+                encoding: *encoding,
+            };
+            for name in &["a", "b"] {
+                let mut params = MPCParameters::new(TestCircuit { x: None }).unwrap();
+                params.contribute(&mut rng, &0);
+                base.circuits.push(CircuitParams {
+                    name: name.to_string(),
+                    fingerprint: None,
+                    params,
+                });
+            }
+
+            let mut file = vec![];
+            base.write(&mut file).unwrap();
+            let mut contributed = Ceremony::read(&file[..], false, UnknownCircuits::Allow).unwrap();
+            contributed.history.push([2u8; 64]);
+            for circuit in &mut contributed.circuits {
+                circuit.params.contribute(&mut rng, &0);
+            }
+
+            let delta = Delta::from_ceremony(&contributed).unwrap();
+            let mut bytes = vec![];
+            delta.write(&mut bytes).unwrap();
+            assert_eq!(delta.serialized_len(), bytes.len() as u64);
+            assert_eq!(Delta::read(&bytes[..], &base).unwrap(), delta);
+            assert!(bytes.len() < file.len());
+
+            let applied = delta.apply(&base, UnknownCircuits::Allow).unwrap();
+            let mut expected = vec![];
+            contributed.write(&mut expected).unwrap();
+            let mut written = vec![];
+            applied.write(&mut written).unwrap();
+            assert_eq!(written, expected);
+            for (before, after) in base.circuits.iter().zip(&applied.circuits) {
+                assert!(verify_contribution(&before.params, &after.params).is_ok());
+            }
+
+            // The queries must fit the params the delta is applied to
+            let mut other = Ceremony::read(&file[..], false, UnknownCircuits::Allow).unwrap();
+            other.circuits.pop();
+            assert!(Delta::read(&bytes[..], &other).is_err());
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Helpers for writing output files.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// BLAKE2b-512 digest of a file's contents, as printed by `b2sum`.
+///
+/// Based on code from the b2sum crate, MIT License Copyright (c) 2017 John Downey
+pub fn file_digest<P: AsRef<Path>>(path: P) -> io::Result<[u8; 64]> {
+    let mut reader = BufReader::with_capacity(1024 * 1024, File::open(path)?);
+    let mut state = blake2b_simd::State::new();
+
+    loop {
+        let count = {
+            let data = reader.fill_buf()?;
+            if data.is_empty() {
+                break;
+            }
+
+            state.update(data);
+            data.len()
+        };
+
+        reader.consume(count);
+    }
+
+    let mut digest = [0u8; 64];
+    digest.copy_from_slice(state.finalize().as_bytes());
+    Ok(digest)
+}
+
+/// A file that is written under a temporary name and renamed into place
+/// once complete, so an interrupted write never leaves a truncated file
+/// behind. The BLAKE2b-512 digest and size of the contents are tracked as
+/// they are written.
+pub struct AtomicFile {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    state: blake2b_simd::State,
+    len: u64,
+}
+
+/// Size and digest of a file written through [`AtomicFile`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub path: PathBuf,
+    pub len: u64,
+    pub hash: [u8; 64],
+}
+
+impl AtomicFile {
+    /// Starts writing `path`. Fails if it already exists, unless `overwrite`
+    /// is set.
+    pub fn create<P: AsRef<Path>>(path: P, overwrite: bool) -> io::Result<AtomicFile> {
+        let path = path.as_ref().to_path_buf();
+        if !overwrite && path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+
+        let mut tmp_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?
+            .to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let f = File::create(&tmp_path)?;
+        Ok(AtomicFile {
+            path,
+            tmp_path,
+            writer: Some(BufWriter::with_capacity(1024 * 1024, f)),
+            state: blake2b_simd::State::new(),
+            len: 0,
+        })
+    }
+
+    /// Flushes the contents to disk and moves the file into place.
+    pub fn commit(mut self) -> io::Result<Summary> {
+        let writer = self.writer.take().expect("writer is only taken on commit");
+        let f = writer.into_inner().map_err(|e| e.into_error())?;
+        f.sync_all()?;
+        drop(f);
+        fs::rename(&self.tmp_path, &self.path)?;
+
+        Ok(Summary {
+            path: self.path.clone(),
+            len: self.len,
+            hash: self.digest(),
+        })
+    }
+
+    /// BLAKE2b-512 digest of the contents written so far.
+    pub fn digest(&self) -> [u8; 64] {
+        let mut hash = [0u8; 64];
+        hash.copy_from_slice(self.state.finalize().as_bytes());
+        hash
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("writer is only taken on commit");
+        let n = writer.write(buf)?;
+        self.state.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer
+            .as_mut()
+            .expect("writer is only taken on commit")
+            .flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // Not committed: discard the partial file
+        if self.writer.take().is_some() {
+            let _ = fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atomic_file() {
+        let dir = std::env::temp_dir().join(format!("masp-mpc-fs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.params");
+
+        let mut f = AtomicFile::create(&path, false).unwrap();
+        f.write_all(b"hello").unwrap();
+        assert!(!path.exists());
+        let summary = f.commit().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"hello");
+        assert_eq!(file_digest(&path).unwrap(), summary.hash);
+        assert_eq!(summary.len, 5);
+        assert_eq!(
+            &summary.hash[..],
+            blake2b_simd::blake2b(b"hello").as_bytes()
+        );
+
+        assert!(AtomicFile::create(&path, false).is_err());
+        let mut f = AtomicFile::create(&path, true).unwrap();
+        f.write_all(b"partial").unwrap();
+        drop(f);
+        assert_eq!(fs::read(&path).unwrap(), b"hello");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
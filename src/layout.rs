@@ -0,0 +1,907 @@
+//! Byte layout of serialized `MPCParameters`.
+//!
+//! `MPCParameters::write` produces the Groth16 parameters (fixed-size
+//! verifying key elements, then the IC, H, L, A, B_G1 and B_G2 queries, each
+//! prefixed with a big-endian u32 length), the 64-byte cs_hash and the
+//! length-prefixed list of contribution public keys. All points are
+//! uncompressed, so every section can be located from the length prefixes
+//! alone, without deserializing anything.
+//!
+//! Ceremony files may instead store the H, L, A, B_G1 and B_G2 queries with
+//! compressed points, see [`Encoding`]. The other sections and the length
+//! prefixes are unchanged, so such files can be scanned the same way.
+
+use crate::batch;
+use crate::ceremony::{invalid_data, Header};
+use crate::circuits::Shape;
+use bellman::groth16::Parameters;
+use bls12_381::{Bls12, G1Affine, G2Affine};
+use byteorder::{BigEndian, ReadBytesExt};
+use masp_phase2::MPCParameters;
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+pub const G1_LEN: usize = 96;
+pub const G2_LEN: usize = 192;
+pub const G1_COMPRESSED_LEN: usize = 48;
+pub const G2_COMPRESSED_LEN: usize = 96;
+pub const CS_HASH_LEN: usize = 64;
+
+/// Serialized public key: delta_after, s and s_delta in G1, r_delta in G2 and
+/// the 64-byte transcript.
+pub const PUBKEY_LEN: usize = 3 * G1_LEN + G2_LEN + 64;
+
+/// Offsets of the fixed-size verifying key elements from the start of the
+/// parameters.
+pub const ALPHA_G1: u64 = 0;
+pub const BETA_G1: u64 = ALPHA_G1 + G1_LEN as u64;
+pub const BETA_G2: u64 = BETA_G1 + G1_LEN as u64;
+pub const GAMMA_G2: u64 = BETA_G2 + G2_LEN as u64;
+pub const DELTA_G1: u64 = GAMMA_G2 + G2_LEN as u64;
+pub const DELTA_G2: u64 = DELTA_G1 + G1_LEN as u64;
+pub const VK_FIXED_LEN: u64 = DELTA_G2 + G2_LEN as u64;
+
+/// Upper bound on the length of any query, the size of the largest
+/// evaluation domain phase 2 supports.
+pub const MAX_QUERY_LEN: usize = 1 << 21;
+
+/// Upper bound on the number of contributions accepted when reading
+/// parameters.
+pub const MAX_CONTRIBUTIONS: usize = 1 << 16;
+
+/// Length of `Parameters::write`'s output.
+pub fn groth16_len(params: &Parameters<Bls12>) -> u64 {
+    groth16_encoded_len(params, Encoding::Uncompressed)
+}
+
+fn groth16_encoded_len(params: &Parameters<Bls12>, encoding: Encoding) -> u64 {
+    let queries = params.h.len() + params.l.len() + params.a.len() + params.b_g1.len();
+    let g1 = params.vk.ic.len() * G1_LEN + queries * encoding.g1_len();
+    let g2 = params.b_g2.len() * encoding.g2_len();
+
+    VK_FIXED_LEN + (6 * 4 + g1 + g2) as u64
+}
+
+/// Length of `MPCParameters::write`'s output.
+pub fn serialized_len(params: &MPCParameters) -> u64 {
+    encoded_len(params, Encoding::Uncompressed)
+}
+
+/// Length of [`write_encoded_params`]'s output.
+pub fn encoded_len(params: &MPCParameters, encoding: Encoding) -> u64 {
+    let contributions = 4 + params.contributions.len() * PUBKEY_LEN;
+    groth16_encoded_len(params.get_params(), encoding) + (CS_HASH_LEN + contributions) as u64
+}
+
+/// How the points of the H, L, A, B_G1 and B_G2 queries are stored.
+///
+/// Compressed points take half the space, but decompressing them takes a
+/// square root per point when reading.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// As written by `MPCParameters::write`.
+    Uncompressed,
+    Compressed,
+}
+
+impl Encoding {
+    /// Length of a G1 point of the encoded queries.
+    pub fn g1_len(self) -> usize {
+        match self {
+            Encoding::Uncompressed => G1_LEN,
+            Encoding::Compressed => G1_COMPRESSED_LEN,
+        }
+    }
+
+    /// Length of a G2 point of the encoded queries.
+    pub fn g2_len(self) -> usize {
+        match self {
+            Encoding::Uncompressed => G2_LEN,
+            Encoding::Compressed => G2_COMPRESSED_LEN,
+        }
+    }
+
+    fn point(self, point: Point) -> Option<Point> {
+        match self {
+            Encoding::Uncompressed => None,
+            Encoding::Compressed => Some(point),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Point {
+    G1,
+    G2,
+}
+
+impl Point {
+    fn len(self) -> usize {
+        match self {
+            Point::G1 => G1_LEN,
+            Point::G2 => G2_LEN,
+        }
+    }
+
+    fn compressed_len(self) -> usize {
+        match self {
+            Point::G1 => G1_COMPRESSED_LEN,
+            Point::G2 => G2_COMPRESSED_LEN,
+        }
+    }
+
+    /// Writes the uncompressed point in `bytes` compressed. Only points on
+    /// the curve can be, as compressing drops y.
+    fn compress<W: Write>(self, bytes: &[u8], mut writer: W) -> io::Result<()> {
+        let invalid = || invalid_data("can't compress a point that is not on the curve");
+        match self {
+            Point::G1 => {
+                let p = G1Affine::from_uncompressed_unchecked(bytes.try_into().unwrap());
+                let p = Option::<G1Affine>::from(p)
+                    .filter(|p| bool::from(p.is_on_curve()))
+                    .ok_or_else(invalid)?;
+                writer.write_all(&p.to_compressed())
+            }
+            Point::G2 => {
+                let p = G2Affine::from_uncompressed_unchecked(bytes.try_into().unwrap());
+                let p = Option::<G2Affine>::from(p)
+                    .filter(|p| bool::from(p.is_on_curve()))
+                    .ok_or_else(invalid)?;
+                writer.write_all(&p.to_compressed())
+            }
+        }
+    }
+
+    /// Decompresses `compressed` into `uncompressed`, returning false if it
+    /// is not the x coordinate of a point on the curve. The subgroup is not
+    /// checked.
+    fn decompress(self, compressed: &[u8], uncompressed: &mut [u8]) -> bool {
+        match self {
+            Point::G1 => {
+                let p = G1Affine::from_compressed_unchecked(compressed.try_into().unwrap());
+                Option::<G1Affine>::from(p)
+                    .map(|p| uncompressed.copy_from_slice(&p.to_uncompressed()))
+                    .is_some()
+            }
+            Point::G2 => {
+                let p = G2Affine::from_compressed_unchecked(compressed.try_into().unwrap());
+                Option::<G2Affine>::from(p)
+                    .map(|p| uncompressed.copy_from_slice(&p.to_uncompressed()))
+                    .is_some()
+            }
+        }
+    }
+}
+
+/// Upper bounds on the lengths declared in serialized parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub ic: usize,
+    pub h: usize,
+    pub l: usize,
+    pub a: usize,
+    pub b_g1: usize,
+    pub b_g2: usize,
+    pub contributions: usize,
+}
+
+impl Limits {
+    /// Limits for the parameters of any circuit.
+    pub fn any() -> Limits {
+        Limits {
+            ic: MAX_QUERY_LEN,
+            h: MAX_QUERY_LEN,
+            l: MAX_QUERY_LEN,
+            a: MAX_QUERY_LEN,
+            b_g1: MAX_QUERY_LEN,
+            b_g2: MAX_QUERY_LEN,
+            contributions: MAX_CONTRIBUTIONS,
+        }
+    }
+
+    /// Limits for the parameters of a circuit with `shape`: IC has an
+    /// element per input, L per auxiliary variable, H fits the evaluation
+    /// domain and A and B have at most one per variable.
+    ///
+    /// These are never above [`Limits::any`]. The shape must not come from
+    /// the file being read, see
+    /// [`UnknownCircuits`](crate::ceremony::UnknownCircuits).
+    pub fn from_shape(shape: &Shape) -> Limits {
+        let inputs = shape.inputs as u64;
+        let aux = shape.aux as u64;
+        // Phase 2 adds a constraint per input before sizing the domain
+        let domain = (shape.constraints as u64 + inputs).next_power_of_two();
+
+        let cap = |n: u64| usize::try_from(n).map_or(MAX_QUERY_LEN, |n| n.min(MAX_QUERY_LEN));
+        Limits {
+            ic: cap(inputs),
+            h: cap(domain),
+            l: cap(aux),
+            a: cap(inputs + aux),
+            b_g1: cap(inputs + aux),
+            b_g2: cap(inputs + aux),
+            contributions: MAX_CONTRIBUTIONS,
+        }
+    }
+
+    /// Fails like [`read_params`] would if a query of the scanned `layout` is
+    /// longer than allowed, without reading any point.
+    pub fn check(&self, layout: &Layout) -> io::Result<()> {
+        let queries = [
+            ("IC", &layout.ic, self.ic),
+            ("H", &layout.h, self.h),
+            ("L", &layout.l, self.l),
+            ("A", &layout.a, self.a),
+            ("B_G1", &layout.b_g1, self.b_g1),
+            ("B_G2", &layout.b_g2, self.b_g2),
+            (
+                "contribution list",
+                &layout.contributions,
+                self.contributions,
+            ),
+        ];
+        for (name, query, max) in queries {
+            if query.len > max {
+                return Err(invalid_data(format!(
+                    "{} has {} elements, at most {} expected",
+                    name, query.len, max
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads `MPCParameters`, failing as soon as a length prefix exceeds
+/// `limits` instead of reading the elements it announces.
+///
+/// With `checked` the points are checked like `MPCParameters::read` does,
+/// but once read and on all [`batch::default_threads`] threads.
+pub fn read_params<R: Read>(reader: R, checked: bool, limits: Limits) -> io::Result<MPCParameters> {
+    read_encoded_params(reader, checked, limits, Encoding::Uncompressed)
+}
+
+/// Like [`read_params`], for parameters written with `encoding`. Compressed
+/// points are decompressed on all [`batch::default_threads`] threads.
+pub fn read_encoded_params<R: Read>(
+    reader: R,
+    checked: bool,
+    limits: Limits,
+    encoding: Encoding,
+) -> io::Result<MPCParameters> {
+    let params = MPCParameters::read(
+        Bounded {
+            inner: reader,
+            sections: sections(limits, encoding).into_iter(),
+            left: 0,
+            point: None,
+            decompressed: 0,
+            compressed: vec![],
+            pending: vec![],
+            pending_pos: 0,
+        },
+        false,
+    )?;
+    if checked {
+        check_points(params.get_params(), batch::default_threads())?;
+    }
+
+    Ok(params)
+}
+
+/// Writes `params` like `MPCParameters::write`, with the queries in
+/// `encoding`.
+pub fn write_encoded_params<W: Write>(
+    params: &MPCParameters,
+    writer: W,
+    encoding: Encoding,
+) -> io::Result<()> {
+    match encoding {
+        Encoding::Uncompressed => params.write(writer),
+        Encoding::Compressed => params.write(Compressing {
+            inner: writer,
+            sections: sections(Limits::any(), encoding).into_iter(),
+            left: 0,
+            point: None,
+            prefixed: None,
+            pending: vec![],
+        }),
+    }
+}
+
+/// Checks that every point of the queries of `params` is on the curve and in
+/// the prime order subgroup, splitting each query between `threads` threads.
+/// The fixed verifying key elements are always checked when reading.
+pub fn check_points(params: &Parameters<Bls12>, threads: usize) -> io::Result<()> {
+    let g1 = |p: &G1Affine| bool::from(p.is_on_curve() & p.is_torsion_free());
+    let g2 = |p: &G2Affine| bool::from(p.is_on_curve() & p.is_torsion_free());
+
+    check_query("IC", &params.vk.ic, threads, g1)?;
+    check_query("H", &params.h, threads, g1)?;
+    check_query("L", &params.l, threads, g1)?;
+    check_query("A", &params.a, threads, g1)?;
+    check_query("B_G1", &params.b_g1, threads, g1)?;
+    check_query("B_G2", &params.b_g2, threads, g2)
+}
+
+fn check_query<T: Sync, F: Fn(&T) -> bool + Sync>(
+    name: &str,
+    points: &[T],
+    threads: usize,
+    valid: F,
+) -> io::Result<()> {
+    let chunk_size = points.len().div_ceil(threads.max(1)).max(1);
+    let valid = &valid;
+    let invalid = std::thread::scope(|scope| {
+        let chunks: Vec<_> = points
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().position(|p| !valid(p))))
+            .collect();
+
+        chunks.into_iter().enumerate().find_map(|(i, chunk)| {
+            let position = chunk.join().expect("point check thread panicked")?;
+            Some(i * chunk_size + position)
+        })
+    });
+
+    match invalid {
+        Some(i) => Err(invalid_data(format!(
+            "{} point {} is not in the prime order subgroup",
+            name, i
+        ))),
+        None => Ok(()),
+    }
+}
+
+enum Section {
+    Fixed(u64),
+    Prefixed {
+        name: &'static str,
+        element_len: usize,
+        max: usize,
+        /// Set if the elements are stored as compressed points.
+        point: Option<Point>,
+    },
+}
+
+impl Section {
+    fn query(name: &'static str, max: usize) -> Section {
+        Section::Prefixed {
+            name,
+            element_len: G1_LEN,
+            max,
+            point: None,
+        }
+    }
+
+    fn points(name: &'static str, point: Point, max: usize, encoding: Encoding) -> Section {
+        Section::Prefixed {
+            name,
+            element_len: point.len(),
+            max,
+            point: encoding.point(point),
+        }
+    }
+}
+
+/// Sections of serialized parameters, as `MPCParameters::read` and
+/// `MPCParameters::write` expect them.
+fn sections(limits: Limits, encoding: Encoding) -> Vec<Section> {
+    vec![
+        Section::Fixed(VK_FIXED_LEN),
+        Section::query("IC", limits.ic),
+        Section::points("H", Point::G1, limits.h, encoding),
+        Section::points("L", Point::G1, limits.l, encoding),
+        Section::points("A", Point::G1, limits.a, encoding),
+        Section::points("B_G1", Point::G1, limits.b_g1, encoding),
+        Section::points("B_G2", Point::G2, limits.b_g2, encoding),
+        Section::Fixed(CS_HASH_LEN as u64),
+        Section::Prefixed {
+            name: "contribution list",
+            element_len: PUBKEY_LEN,
+            max: limits.contributions,
+            point: None,
+        },
+    ]
+}
+
+/// Number of compressed points decompressed at once.
+const DECOMPRESS_BATCH: usize = 1 << 16;
+
+/// Reader passing serialized parameters through section by section, checking
+/// each length prefix before passing it on and decompressing compressed
+/// sections. It never reads past the end of the parameters.
+struct Bounded<R> {
+    inner: R,
+    sections: std::vec::IntoIter<Section>,
+    /// Bytes of `inner` left in the current section, after its length prefix.
+    left: u64,
+    /// Name and type of the current section's points, if compressed.
+    point: Option<(&'static str, Point)>,
+    /// Points of the current section decompressed so far.
+    decompressed: usize,
+    compressed: Vec<u8>,
+    /// Length prefix or decompressed points, passed on from `pending_pos`.
+    pending: Vec<u8>,
+    pending_pos: usize,
+}
+
+impl<R: Read> Bounded<R> {
+    fn decompress_batch(&mut self, name: &str, point: Point) -> io::Result<()> {
+        let count = usize::try_from(self.left / point.compressed_len() as u64)
+            .map_or(DECOMPRESS_BATCH, |left| left.min(DECOMPRESS_BATCH));
+        self.compressed.resize(count * point.compressed_len(), 0);
+        self.inner.read_exact(&mut self.compressed)?;
+        self.pending.resize(count * point.len(), 0);
+        self.pending_pos = 0;
+
+        let invalid = decompress(
+            point,
+            &self.compressed,
+            &mut self.pending,
+            batch::default_threads(),
+        );
+        if let Some(i) = invalid {
+            return Err(invalid_data(format!(
+                "{} point {} is not a valid compressed point",
+                name,
+                self.decompressed + i
+            )));
+        }
+
+        self.left -= self.compressed.len() as u64;
+        self.decompressed += count;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Bounded<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            if self.pending_pos < self.pending.len() {
+                let n = (self.pending.len() - self.pending_pos).min(buf.len());
+                buf[..n].copy_from_slice(&self.pending[self.pending_pos..self.pending_pos + n]);
+                self.pending_pos += n;
+                return Ok(n);
+            }
+            if self.left > 0 {
+                if let Some((name, point)) = self.point {
+                    self.decompress_batch(name, point)?;
+                    continue;
+                }
+                let len = usize::try_from(self.left).map_or(buf.len(), |left| left.min(buf.len()));
+                let n = self.inner.read(&mut buf[..len])?;
+                self.left -= n as u64;
+                return Ok(n);
+            }
+
+            match self.sections.next() {
+                None => return Ok(0),
+                Some(Section::Fixed(len)) => {
+                    self.left = len;
+                    self.point = None;
+                }
+                Some(Section::Prefixed {
+                    name,
+                    element_len,
+                    max,
+                    point,
+                }) => {
+                    let mut prefix = [0u8; 4];
+                    self.inner.read_exact(&mut prefix)?;
+                    let len = u32::from_be_bytes(prefix) as usize;
+                    if len > max {
+                        return Err(invalid_data(format!(
+                            "{} has {} elements, at most {} expected",
+                            name, len, max
+                        )));
+                    }
+                    self.pending = prefix.to_vec();
+                    self.pending_pos = 0;
+                    let element_len = point.map_or(element_len, Point::compressed_len);
+                    self.left = len as u64 * element_len as u64;
+                    self.point = point.map(|point| (name, point));
+                    self.decompressed = 0;
+                }
+            }
+        }
+    }
+}
+
+/// Decompresses the points of `compressed` into `uncompressed`, splitting
+/// them between `threads` threads. Returns the index of the first invalid
+/// point.
+fn decompress(
+    point: Point,
+    compressed: &[u8],
+    uncompressed: &mut [u8],
+    threads: usize,
+) -> Option<usize> {
+    let count = compressed.len() / point.compressed_len();
+    let chunk_size = count.div_ceil(threads.max(1)).max(1);
+    std::thread::scope(|scope| {
+        let chunks: Vec<_> = compressed
+            .chunks(chunk_size * point.compressed_len())
+            .zip(uncompressed.chunks_mut(chunk_size * point.len()))
+            .map(|(compressed, uncompressed)| {
+                scope.spawn(move || {
+                    compressed
+                        .chunks(point.compressed_len())
+                        .zip(uncompressed.chunks_mut(point.len()))
+                        .position(|(c, u)| !point.decompress(c, u))
+                })
+            })
+            .collect();
+
+        chunks.into_iter().enumerate().find_map(|(i, chunk)| {
+            let position = chunk.join().expect("decompression thread panicked")?;
+            Some(i * chunk_size + position)
+        })
+    })
+}
+
+/// Writer compressing the points of the compressed sections of the
+/// parameters `MPCParameters::write` writes to it.
+struct Compressing<W> {
+    inner: W,
+    sections: std::vec::IntoIter<Section>,
+    /// Bytes left in the current section, after its length prefix.
+    left: u64,
+    /// Type of the current section's points, if compressed.
+    point: Option<Point>,
+    /// Element length and compression of the section whose length prefix is
+    /// being written.
+    prefixed: Option<(usize, Option<Point>)>,
+    /// Partial length prefix or point.
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Write for Compressing<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            if let Some((element_len, point)) = self.prefixed {
+                let n = (4 - self.pending.len()).min(buf.len());
+                self.pending.extend_from_slice(&buf[..n]);
+                if self.pending.len() == 4 {
+                    self.inner.write_all(&self.pending)?;
+                    let len = u32::from_be_bytes(self.pending[..].try_into().unwrap());
+                    self.left = len as u64 * element_len as u64;
+                    self.point = point;
+                    self.prefixed = None;
+                    self.pending.clear();
+                }
+                return Ok(n);
+            }
+            if self.left > 0 {
+                let len = usize::try_from(self.left).map_or(buf.len(), |left| left.min(buf.len()));
+                let point = match self.point {
+                    None => {
+                        let n = self.inner.write(&buf[..len])?;
+                        self.left -= n as u64;
+                        return Ok(n);
+                    }
+                    Some(point) => point,
+                };
+
+                let n = len.min(point.len() - self.pending.len());
+                self.pending.extend_from_slice(&buf[..n]);
+                self.left -= n as u64;
+                if self.pending.len() == point.len() {
+                    point.compress(&self.pending, &mut self.inner)?;
+                    self.pending.clear();
+                }
+                return Ok(n);
+            }
+
+            match self.sections.next() {
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "data written past the end of the parameters",
+                    ))
+                }
+                Some(Section::Fixed(len)) => {
+                    self.left = len;
+                    self.point = None;
+                }
+                Some(Section::Prefixed {
+                    element_len, point, ..
+                }) => self.prefixed = Some((element_len, point)),
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A length-prefixed vector of fixed-size elements.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Query {
+    /// Absolute offset of the first element, just after the length prefix.
+    pub offset: u64,
+    pub len: usize,
+    pub element_len: usize,
+}
+
+impl Query {
+    fn scan<R: Read + Seek>(reader: &mut R, element_len: usize) -> io::Result<Query> {
+        let len = reader.read_u32::<BigEndian>()? as usize;
+        let offset = reader.stream_position()?;
+        let query = Query {
+            offset,
+            len,
+            element_len,
+        };
+        reader.seek(SeekFrom::Start(query.end()))?;
+        Ok(query)
+    }
+
+    /// Absolute offset just past the last element.
+    pub fn end(&self) -> u64 {
+        self.offset + self.len as u64 * self.element_len as u64
+    }
+}
+
+/// Location of every section of one circuit's serialized `MPCParameters`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    /// Absolute offset of the parameters, i.e. of alpha_g1.
+    pub start: u64,
+    pub ic: Query,
+    pub h: Query,
+    pub l: Query,
+    pub a: Query,
+    pub b_g1: Query,
+    pub b_g2: Query,
+    pub cs_hash: u64,
+    pub contributions: Query,
+}
+
+impl Layout {
+    /// Walks the length prefixes of the parameters starting at the reader's
+    /// current position, leaving the reader just past them.
+    pub fn scan<R: Read + Seek>(reader: R) -> io::Result<Layout> {
+        Layout::scan_encoded(reader, Encoding::Uncompressed)
+    }
+
+    /// Like [`Layout::scan`], for parameters written with `encoding`.
+    pub fn scan_encoded<R: Read + Seek>(mut reader: R, encoding: Encoding) -> io::Result<Layout> {
+        let start = reader.stream_position()?;
+        reader.seek(SeekFrom::Start(start + VK_FIXED_LEN))?;
+
+        let ic = Query::scan(&mut reader, G1_LEN)?;
+        let h = Query::scan(&mut reader, encoding.g1_len())?;
+        let l = Query::scan(&mut reader, encoding.g1_len())?;
+        let a = Query::scan(&mut reader, encoding.g1_len())?;
+        let b_g1 = Query::scan(&mut reader, encoding.g1_len())?;
+        let b_g2 = Query::scan(&mut reader, encoding.g2_len())?;
+
+        let cs_hash = b_g2.end();
+        reader.seek(SeekFrom::Start(cs_hash + CS_HASH_LEN as u64))?;
+
+        let contributions = Query::scan(&mut reader, PUBKEY_LEN)?;
+
+        Ok(Layout {
+            start,
+            ic,
+            h,
+            l,
+            a,
+            b_g1,
+            b_g2,
+            cs_hash,
+            contributions,
+        })
+    }
+
+    /// Absolute offset just past the parameters.
+    pub fn end(&self) -> u64 {
+        self.contributions.end()
+    }
+}
+
+/// Layout of a whole ceremony file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileLayout {
+    pub header: Header,
+    pub circuits: Vec<Layout>,
+}
+
+impl FileLayout {
+    /// Reads the header and locates the parameters of every circuit.
+    pub fn scan<R: Read + Seek>(mut reader: R) -> io::Result<FileLayout> {
+        let file_len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let header = Header::read(&mut reader)?;
+        let mut circuits = Vec::with_capacity(header.circuits.len());
+        for _ in &header.circuits {
+            let layout = Layout::scan_encoded(&mut reader, header.encoding)?;
+            if layout.end() > file_len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "ceremony file is truncated",
+                ));
+            }
+            circuits.push(layout);
+        }
+
+        if reader.stream_position()? != file_len {
+            return Err(invalid_data("trailing data after ceremony file"));
+        }
+
+        Ok(FileLayout { header, circuits })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ceremony::{Ceremony, CircuitParams, UnknownCircuits};
+    use crate::TestCircuit;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use std::io::Cursor;
+
+    #[test]
+    fn scan_matches_deserialization() {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+        let mut ceremony = Ceremony {
+            circuits: vec![],
+            history: vec![],
+            encoding: Encoding::Uncompressed,
+        };
+        for name in &["a", "b"] {
+            let mut params = MPCParameters::new(TestCircuit { x: None }).unwrap();
+            params.contribute(&mut rng, &0);
+            params.contribute(&mut rng, &0);
+            ceremony.circuits.push(CircuitParams {
+                name: name.to_string(),
+                fingerprint: None,
+                params,
+            });
+        }
+
+        let mut file = vec![];
+        ceremony.write(&mut file).unwrap();
+        assert_eq!(ceremony.serialized_len(), file.len() as u64);
+
+        let mut reader = Cursor::new(&file[..]);
+        let layout = FileLayout::scan(&mut reader).unwrap();
+        assert_eq!(layout.header, ceremony.header());
+        assert_eq!(layout.circuits.len(), 2);
+
+        for circuit in &layout.circuits {
+            assert_eq!(circuit.contributions.len, 2);
+        }
+
+        // Each circuit's parameters start where the previous ones end
+        let second = &layout.circuits[1];
+        let mut reader = Cursor::new(&file[second.start as usize..second.end() as usize]);
+        let params = MPCParameters::read(&mut reader, false).unwrap();
+        assert_eq!(reader.position(), second.end() - second.start);
+        assert_eq!(serialized_len(&params), second.end() - second.start);
+
+        assert!(FileLayout::scan(Cursor::new(&file[..file.len() - 1])).is_err());
+    }
+
+    #[test]
+    fn read_params_limits() {
+        let mut params = MPCParameters::new(TestCircuit { x: None }).unwrap();
+        params.contribute(&mut ChaChaRng::from_seed([0u8; 32]), &0);
+        let mut bytes = vec![];
+        params.write(&mut bytes).unwrap();
+        let len = bytes.len();
+        bytes.push(0xff);
+
+        // x = x^2 has one constraint, the input one and an auxiliary variable
+        let limits = Limits::from_shape(&Shape {
+            constraints: 1,
+            inputs: 1,
+            aux: 1,
+        });
+        let mut reader = Cursor::new(&bytes[..]);
+        let read = read_params(&mut reader, true, limits).unwrap();
+        assert_eq!(reader.position(), len as u64);
+        let mut written = vec![];
+        read.write(&mut written).unwrap();
+        assert_eq!(written[..], bytes[..len]);
+
+        let limits = Limits {
+            contributions: 0,
+            ..limits
+        };
+        let err = read_params(&bytes[..], false, limits).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // The scanned layout is checked the same way
+        let layout = Layout::scan(Cursor::new(&bytes[..])).unwrap();
+        assert!(Limits::any().check(&layout).is_ok());
+        assert!(limits.check(&layout).is_err());
+
+        // Rejected before the reader runs out of data
+        let prefix = layout.h.offset as usize - 4;
+        bytes[prefix..prefix + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let err = read_params(&bytes[..], false, Limits::any()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        let mut ceremony = Ceremony {
+            circuits: vec![],
+            history: vec![[3u8; 64]],
+            encoding: Encoding::Compressed,
+        };
+        let mut params = MPCParameters::new(TestCircuit { x: None }).unwrap();
+        params.contribute(&mut ChaChaRng::from_seed([0u8; 32]), &0);
+        ceremony.circuits.push(CircuitParams {
+            name: "a".to_string(),
+            fingerprint: None,
+            params,
+        });
+
+        let mut file = vec![];
+        ceremony.write(&mut file).unwrap();
+        assert_eq!(ceremony.serialized_len(), file.len() as u64);
+
+        let layout = FileLayout::scan(Cursor::new(&file[..])).unwrap();
+        assert_eq!(layout.header.encoding, Encoding::Compressed);
+        let circuit = &layout.circuits[0];
+        assert_eq!(circuit.h.element_len, G1_COMPRESSED_LEN);
+        assert_eq!(circuit.b_g2.element_len, G2_COMPRESSED_LEN);
+        assert_eq!(circuit.ic.element_len, G1_LEN);
+
+        let read = Ceremony::read(&file[..], true, UnknownCircuits::Allow).unwrap();
+        assert_eq!(read.header(), ceremony.header());
+        let mut expected = vec![];
+        ceremony.circuits[0].params.write(&mut expected).unwrap();
+        let mut written = vec![];
+        read.circuits[0].params.write(&mut written).unwrap();
+        assert_eq!(written, expected);
+        assert!(file.len() < expected.len());
+
+        // Not a valid compressed point
+        let mut bad = file.clone();
+        let l = circuit.l.offset as usize;
+        bad[l..l + G1_COMPRESSED_LEN].fill(0xff);
+        let err = Ceremony::read(&bad[..], false, UnknownCircuits::Allow)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parallel_point_checks() {
+        let mut params = MPCParameters::new(TestCircuit { x: None }).unwrap();
+        params.contribute(&mut ChaChaRng::from_seed([0u8; 32]), &0);
+        let mut bytes = vec![];
+        params.write(&mut bytes).unwrap();
+
+        let read = read_params(&bytes[..], true, Limits::any()).unwrap();
+        let mut written = vec![];
+        read.write(&mut written).unwrap();
+        assert_eq!(written, bytes);
+        for threads in 1..4 {
+            check_points(read.get_params(), threads).unwrap();
+        }
+
+        // Moving the first L point off the curve
+        let layout = Layout::scan(Cursor::new(&bytes[..])).unwrap();
+        bytes[layout.l.offset as usize + G1_LEN - 1] ^= 1;
+        assert!(MPCParameters::read(&bytes[..], true).is_err());
+        assert!(read_params(&bytes[..], false, Limits::any()).is_ok());
+        let err = read_params(&bytes[..], true, Limits::any()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
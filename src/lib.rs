@@ -1,13 +1,52 @@
+pub mod batch;
+pub mod cache;
+pub mod ceremony;
+pub mod chunks;
+pub mod circuits;
+pub mod delta;
+pub mod fs;
+pub mod layout;
+pub mod mmap;
+pub mod progress;
+
 use bellman::{Circuit, ConstraintSystem, SynthesisError};
 use bls12_381::Scalar;
 
-struct TestCircuit {
-    x: Option<Scalar>,
+/// Formats `h` as lowercase hex.
+pub fn into_hex(h: &[u8]) -> String {
+    let mut f = String::new();
+
+    for byte in h {
+        f += &format!("{:02x}", byte);
+    }
+
+    f
 }
+
+/// Parses a 64-byte hash printed as hex, with or without a `0x` prefix.
+pub fn parse_hash(s: &str) -> Option<[u8; 64]> {
+    let s = s.trim();
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    let bytes = hex::decode(s).ok()?;
+    if bytes.len() != 64 {
+        return None;
+    }
+
+    let mut hash = [0u8; 64];
+    hash.copy_from_slice(&bytes);
+    Some(hash)
+}
+
+/// Circuit with the single constraint `x = x^2`, small enough to run a whole
+/// ceremony in tests.
+pub struct TestCircuit {
+    pub x: Option<Scalar>,
+}
+
 impl Circuit<Scalar> for TestCircuit {
     fn synthesize<CS: ConstraintSystem<Scalar>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
-        let mut x_value = self.x;
-        let mut x = cs.alloc(|| "x", || x_value.ok_or(SynthesisError::AssignmentMissing))?;
+        let x_value = self.x;
+        let x = cs.alloc(|| "x", || x_value.ok_or(SynthesisError::AssignmentMissing))?;
 
         cs.enforce(|| "x = x^2", |lc| lc + x, |lc| lc + x, |lc| lc + x);
 
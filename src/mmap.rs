@@ -0,0 +1,251 @@
+//! Memory-mapped ceremony files.
+//!
+//! Reading a ceremony file deserializes every point of every circuit.
+//! Commands that only need part of a file, such as its contribution lists or
+//! verifying keys, can instead map it and look at the sections they need, so
+//! only those are paged in.
+
+use crate::ceremony::{CircuitEntry, Header, UnknownCircuits};
+use crate::layout::{
+    self, Encoding, FileLayout, Layout, Query, CS_HASH_LEN, DELTA_G1, DELTA_G2, PUBKEY_LEN,
+    VK_FIXED_LEN,
+};
+use bellman::groth16::VerifyingKey;
+use blake2::{Blake2b512, Digest};
+use bls12_381::Bls12;
+use masp_phase2::MPCParameters;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, Cursor};
+use std::path::Path;
+
+/// A ceremony file mapped into memory, with its sections located.
+pub struct MappedCeremony {
+    map: Mmap,
+    pub layout: FileLayout,
+}
+
+impl MappedCeremony {
+    /// Maps the ceremony file at `path`.
+    ///
+    /// The file must not be modified while it is mapped. Every command
+    /// replaces params files by renaming a new file into place, which leaves
+    /// the mapped one untouched.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<MappedCeremony> {
+        let f = File::open(path)?;
+        // Safety: see above, a file truncated while mapped would make
+        // accessing the missing pages fail with SIGBUS
+        let map = unsafe { Mmap::map(&f)? };
+        let layout = FileLayout::scan(Cursor::new(&map[..]))?;
+        Ok(MappedCeremony { map, layout })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.layout.header
+    }
+
+    /// The whole file.
+    pub fn bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    pub fn circuits(&self) -> Vec<MappedParams<'_>> {
+        self.header()
+            .circuits
+            .iter()
+            .zip(&self.layout.circuits)
+            .map(|(entry, layout)| MappedParams {
+                file: &self.map,
+                entry,
+                layout,
+                encoding: self.header().encoding,
+            })
+            .collect()
+    }
+
+    /// Hashes of the contribution public keys, per circuit, as
+    /// [`Ceremony::contributions`](crate::ceremony::Ceremony::contributions)
+    /// returns them.
+    pub fn contributions(&self) -> Vec<Vec<[u8; 64]>> {
+        self.circuits()
+            .iter()
+            .map(|c| c.contribution_hashes())
+            .collect()
+    }
+}
+
+/// The serialized parameters of one circuit of a [`MappedCeremony`].
+pub struct MappedParams<'a> {
+    file: &'a [u8],
+    pub entry: &'a CircuitEntry,
+    pub layout: &'a Layout,
+    pub encoding: Encoding,
+}
+
+impl<'a> MappedParams<'a> {
+    pub fn name(&self) -> &'a str {
+        &self.entry.name
+    }
+
+    /// The serialized parameters, in the file's encoding.
+    pub fn bytes(&self) -> &'a [u8] {
+        &self.file[self.layout.start as usize..self.layout.end() as usize]
+    }
+
+    /// The elements of `query`, one of the queries of [`MappedParams::layout`],
+    /// without the length prefix.
+    pub fn query(&self, query: &Query) -> &'a [u8] {
+        &self.file[query.offset as usize..query.end() as usize]
+    }
+
+    /// delta_g1, uncompressed.
+    pub fn delta_g1(&self) -> &'a [u8] {
+        let start = self.layout.start + DELTA_G1;
+        &self.file[start as usize..(start + DELTA_G2 - DELTA_G1) as usize]
+    }
+
+    /// delta_g2, uncompressed.
+    pub fn delta_g2(&self) -> &'a [u8] {
+        let start = self.layout.start + DELTA_G2;
+        &self.file[start as usize..(start + VK_FIXED_LEN - DELTA_G2) as usize]
+    }
+
+    pub fn cs_hash(&self) -> &'a [u8] {
+        let start = self.layout.cs_hash as usize;
+        &self.file[start..start + CS_HASH_LEN]
+    }
+
+    /// The serialized public keys of the contributions, oldest first.
+    pub fn pubkeys(&self) -> std::slice::ChunksExact<'a, u8> {
+        self.query(&self.layout.contributions)
+            .chunks_exact(PUBKEY_LEN)
+    }
+
+    /// Hashes of the contribution public keys, the same way
+    /// `MPCParameters::verify` reports them.
+    pub fn contribution_hashes(&self) -> Vec<[u8; 64]> {
+        self.pubkeys()
+            .map(|pubkey| {
+                let mut hash = [0u8; 64];
+                hash.copy_from_slice(Blake2b512::digest(pubkey).as_ref());
+                hash
+            })
+            .collect()
+    }
+
+    /// The Groth16 parameters as `Parameters::write` writes them, which
+    /// start the parameters of uncompressed files.
+    pub fn groth16_bytes(&self) -> Option<&'a [u8]> {
+        match self.encoding {
+            Encoding::Uncompressed => {
+                Some(&self.file[self.layout.start as usize..self.layout.cs_hash as usize])
+            }
+            Encoding::Compressed => None,
+        }
+    }
+
+    /// The verifying key as `VerifyingKey::write` writes it: the fixed
+    /// elements followed by the IC query, which are never compressed.
+    pub fn vk_bytes(&self) -> &'a [u8] {
+        &self.file[self.layout.start as usize..self.layout.ic.end() as usize]
+    }
+
+    /// Deserializes the verifying key alone.
+    pub fn verifying_key(&self) -> io::Result<VerifyingKey<Bls12>> {
+        VerifyingKey::read(self.vk_bytes())
+    }
+
+    /// Deserializes the whole parameters, see [`layout::read_params`].
+    pub fn read_params(
+        &self,
+        checked: bool,
+        unknown: UnknownCircuits,
+    ) -> io::Result<MPCParameters> {
+        let limits = unknown.limits(self.entry)?;
+        layout::read_encoded_params(self.bytes(), checked, limits, self.encoding)
+    }
+
+    /// Checks the circuit against `unknown` and its sections against the
+    /// resulting limits, like [`MappedParams::read_params`] does, but without
+    /// reading any point. Required before copying [`MappedParams::bytes`]
+    /// out as they are.
+    pub fn check_limits(&self, unknown: UnknownCircuits) -> io::Result<()> {
+        unknown.limits(self.entry)?.check(self.layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ceremony::{Ceremony, CircuitParams};
+    use crate::TestCircuit;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    #[test]
+    fn mapped_sections() {
+        let dir = std::env::temp_dir().join(format!("masp-mpc-mmap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+
+        for encoding in &[Encoding::Uncompressed, Encoding::Compressed] {
+            let mut ceremony = Ceremony {
+                circuits: vec![],
+                history: vec![],
+                encoding: *encoding,
+            };
+            for name in &["a", "b"] {
+                let mut params = MPCParameters::new(TestCircuit { x: None }).unwrap();
+                params.contribute(&mut rng, &0);
+                params.contribute(&mut rng, &0);
+                ceremony.circuits.push(CircuitParams {
+                    name: name.to_string(),
+                    fingerprint: None,
+                    params,
+                });
+            }
+            let path = dir.join("round.params");
+            let mut f = File::create(&path).unwrap();
+            ceremony.write(&mut f).unwrap();
+            drop(f);
+
+            let mapped = MappedCeremony::open(&path).unwrap();
+            assert_eq!(mapped.header(), &ceremony.header());
+            assert_eq!(mapped.contributions(), ceremony.contributions());
+
+            for (circuit, expected) in mapped.circuits().iter().zip(&ceremony.circuits) {
+                let params = expected.params.get_params();
+                assert!(circuit.verifying_key().unwrap() == params.vk);
+                let mut vk = vec![];
+                params.vk.write(&mut vk).unwrap();
+                assert_eq!(circuit.vk_bytes(), &vk[..]);
+                if let Some(bytes) = circuit.groth16_bytes() {
+                    let mut groth16 = vec![];
+                    params.write(&mut groth16).unwrap();
+                    assert_eq!(bytes, &groth16[..]);
+                }
+                assert_eq!(
+                    circuit.delta_g1(),
+                    &params.vk.delta_g1.to_uncompressed()[..]
+                );
+                assert_eq!(
+                    circuit.delta_g2(),
+                    &params.vk.delta_g2.to_uncompressed()[..]
+                );
+                assert_eq!(
+                    circuit.query(&circuit.layout.h).len(),
+                    params.h.len() * encoding.g1_len()
+                );
+
+                let read = circuit.read_params(true, UnknownCircuits::Allow).unwrap();
+                let mut written = vec![];
+                read.write(&mut written).unwrap();
+                let mut expected_bytes = vec![];
+                expected.params.write(&mut expected_bytes).unwrap();
+                assert_eq!(written, expected_bytes);
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
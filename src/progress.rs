@@ -0,0 +1,359 @@
+//! Progress reporting for long-running commands.
+//!
+//! Reports go to stderr, leaving stdout to each command's results. By default
+//! they are human readable lines, at most one per task and second; `--json`
+//! prints one JSON object per line instead and `--quiet` silences them.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Minimum time between two reports of the same task.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Bytes read or written between two updates of a reader's or writer's
+/// progress, so small reads don't each look at the clock.
+const UPDATE_BYTES: u64 = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Human,
+    Json,
+    Quiet,
+}
+
+impl Mode {
+    /// Removes `--quiet` and `--json` from `args` and returns the mode they
+    /// select.
+    pub fn from_args(args: &mut Vec<String>) -> Mode {
+        let mut mode = Mode::Human;
+        args.retain(|arg| match arg.as_str() {
+            "--quiet" => {
+                mode = Mode::Quiet;
+                false
+            }
+            "--json" => {
+                mode = Mode::Json;
+                false
+            }
+            _ => true,
+        });
+        mode
+    }
+
+    /// Reports a one-off event of `task`.
+    pub fn message(self, task: &str, message: &str) {
+        match self {
+            Mode::Human => eprintln!("{}: {}", task, message),
+            Mode::Json => eprintln!(
+                "{{\"task\":{},\"message\":{}}}",
+                json_string(task),
+                json_string(message)
+            ),
+            Mode::Quiet => {}
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    Bytes,
+    Points,
+}
+
+/// Progress of one task towards a known total.
+pub struct Progress {
+    mode: Mode,
+    task: String,
+    unit: Unit,
+    total: u64,
+    done: u64,
+    start: Instant,
+    last_report: Instant,
+}
+
+impl Progress {
+    pub fn new<S: Into<String>>(mode: Mode, task: S, unit: Unit, total: u64) -> Progress {
+        let start = Instant::now();
+        Progress {
+            mode,
+            task: task.into(),
+            unit,
+            total,
+            done: 0,
+            start,
+            last_report: start,
+        }
+    }
+
+    /// Records that `done` units are done, reporting if it is time to.
+    pub fn set(&mut self, done: u64) {
+        self.done = done.min(self.total);
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.report(false);
+        }
+    }
+
+    pub fn add(&mut self, n: u64) {
+        self.set(self.done + n);
+    }
+
+    /// Reports the task as done.
+    pub fn finish(&mut self) {
+        self.done = self.total;
+        self.report(true);
+    }
+
+    /// Estimated time left, from the average rate so far.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.done == 0 {
+            return None;
+        }
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let left = (self.total - self.done) as f64 * elapsed / self.done as f64;
+        Some(Duration::from_secs_f64(left))
+    }
+
+    fn report(&mut self, finished: bool) {
+        self.last_report = Instant::now();
+        let elapsed = self.start.elapsed();
+        let percent = match self.total {
+            0 => 100.0,
+            total => self.done as f64 * 100.0 / total as f64,
+        };
+
+        match self.mode {
+            Mode::Human if finished => eprintln!(
+                "{}: done, {} in {}",
+                self.task,
+                self.unit.format(self.total),
+                format_duration(elapsed)
+            ),
+            Mode::Human => eprintln!(
+                "{}: {:.1}% ({} of {}), ETA {}",
+                self.task,
+                percent,
+                self.unit.format(self.done),
+                self.unit.format(self.total),
+                self.eta().map_or("unknown".to_string(), format_duration)
+            ),
+            Mode::Json => eprintln!(
+                "{{\"task\":{},\"unit\":\"{}\",\"done\":{},\"total\":{},\"percent\":{:.1},\"elapsed\":{:.1},\"eta\":{},\"finished\":{}}}",
+                json_string(&self.task),
+                self.unit.name(),
+                self.done,
+                self.total,
+                percent,
+                elapsed.as_secs_f64(),
+                self.eta().map_or("null".to_string(), |eta| format!("{:.1}", eta.as_secs_f64())),
+                finished
+            ),
+            Mode::Quiet => {}
+        }
+    }
+}
+
+impl Unit {
+    fn name(self) -> &'static str {
+        match self {
+            Unit::Bytes => "bytes",
+            Unit::Points => "points",
+        }
+    }
+
+    fn format(self, n: u64) -> String {
+        match self {
+            Unit::Bytes => format!("{:.1} MiB", n as f64 / (1024.0 * 1024.0)),
+            Unit::Points => format!("{} points", n),
+        }
+    }
+}
+
+/// Reader reporting the bytes read, or the position sought to.
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Progress,
+    pos: u64,
+}
+
+impl<R> ProgressReader<R> {
+    pub fn new(inner: R, progress: Progress) -> ProgressReader<R> {
+        ProgressReader {
+            inner,
+            progress,
+            pos: 0,
+        }
+    }
+
+    /// Reports the read as done and returns the inner reader.
+    pub fn finish(mut self) -> R {
+        self.progress.finish();
+        self.inner
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pos += n as u64;
+        if self.pos >= self.progress.done + UPDATE_BYTES {
+            self.progress.set(self.pos);
+        }
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for ProgressReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        self.progress.set(self.pos);
+        Ok(self.pos)
+    }
+}
+
+/// Writer reporting the bytes written.
+pub struct ProgressWriter<W> {
+    inner: W,
+    progress: Progress,
+    written: u64,
+}
+
+impl<W> ProgressWriter<W> {
+    pub fn new(inner: W, progress: Progress) -> ProgressWriter<W> {
+        ProgressWriter {
+            inner,
+            progress,
+            written: 0,
+        }
+    }
+
+    /// Reports the write as done and returns the inner writer.
+    pub fn finish(mut self) -> W {
+        self.progress.finish();
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        if self.written >= self.progress.done + UPDATE_BYTES {
+            self.progress.set(self.written);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Opens `path` for buffered reading, reporting progress as "reading <path>".
+/// Call `into_inner().finish()` once done.
+pub fn open<P: AsRef<Path>>(mode: Mode, path: P) -> io::Result<BufReader<ProgressReader<File>>> {
+    let path = path.as_ref();
+    let f = File::open(path)?;
+    let progress = Progress::new(
+        mode,
+        format!("reading {}", path.display()),
+        Unit::Bytes,
+        f.metadata()?.len(),
+    );
+    Ok(BufReader::with_capacity(
+        1024 * 1024,
+        ProgressReader::new(f, progress),
+    ))
+}
+
+/// Wraps `inner`, reporting progress towards `total` bytes as
+/// "writing <path>". Call `finish()` once done.
+pub fn writer<W, P: AsRef<Path>>(mode: Mode, path: P, total: u64, inner: W) -> ProgressWriter<W> {
+    let task = format!("writing {}", path.as_ref().display());
+    ProgressWriter::new(inner, Progress::new(mode, task, Unit::Bytes, total))
+}
+
+/// Runs `work`, which does `points` units of `progress` but reports none of
+/// its own, and meanwhile advances `progress` at `rate` units per second.
+/// The estimate stops one unit short of `points` until `work` returns.
+pub fn estimated<T, F>(progress: &mut Progress, points: u64, rate: f64, work: F) -> T
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    let start = progress.done;
+    let began = Instant::now();
+    let (done, finished) = mpsc::channel();
+    std::thread::scope(|scope| {
+        let worker = scope.spawn(move || {
+            let result = work();
+            let _ = done.send(());
+            result
+        });
+        // A panicking worker drops `done`, which also ends the wait
+        while let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(REPORT_INTERVAL) {
+            let estimate = (began.elapsed().as_secs_f64() * rate) as u64;
+            progress.set(start + estimate.min(points.saturating_sub(1)));
+        }
+        let result = worker
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        progress.set(start + points);
+        result
+    })
+}
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match secs {
+        0..=59 => format!("{:.1}s", d.as_secs_f64()),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs / 60 % 60),
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_modes() {
+        let mut args: Vec<String> = vec!["bin", "--json", "a", "--quiet", "b"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(Mode::from_args(&mut args), Mode::Quiet);
+        assert_eq!(args, vec!["bin", "a", "b"]);
+
+        let mut progress = Progress::new(Mode::Quiet, "test", Unit::Bytes, 10);
+        assert_eq!(progress.eta(), None);
+        progress.set(20);
+        assert_eq!(progress.done, 10);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(0)));
+
+        let mut progress = Progress::new(Mode::Quiet, "test", Unit::Points, 10);
+        progress.set(2);
+        assert_eq!(estimated(&mut progress, 5, 1e9, || 7), 7);
+        assert_eq!(progress.done, 7);
+
+        assert_eq!(json_string("a \"b\"\n"), "\"a \\\"b\\\"\\u000a\"");
+        assert_eq!(format_duration(Duration::from_secs(3725)), "1h 02m");
+    }
+}
@@ -0,0 +1,572 @@
+//! Runs whole ceremonies and checks that every way of looking at the result
+//! agrees: the hashes printed while contributing, the hashes found by
+//! verification, the history of file digests and the split parameters. The
+//! binaries are run wherever they work without the MASP circuits.
+
+use masp_mpc::batch;
+use masp_mpc::ceremony::{combined_hashes, Ceremony, CircuitParams, UnknownCircuits};
+use masp_mpc::fs::{file_digest, AtomicFile};
+use masp_mpc::layout::{self, Encoding, FileLayout};
+use masp_mpc::{into_hex, parse_hash, TestCircuit};
+use masp_phase2::{verify_contribution, MPCParameters};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const CONTRIBUTIONS: usize = 3;
+
+/// Empty directory for the files of one test.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("masp-mpc-{}-{}", name, std::process::id()));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn read(path: &Path, checked: bool) -> Ceremony {
+    let f = BufReader::new(File::open(path).unwrap());
+    Ceremony::read(f, checked, UnknownCircuits::Allow).unwrap()
+}
+
+fn write(ceremony: &Ceremony, path: &Path) -> [u8; 64] {
+    let mut f = AtomicFile::create(path, false).unwrap();
+    ceremony.write(&mut f).unwrap();
+    f.commit().unwrap().hash
+}
+
+/// Runs the binary at `bin` with `args`, returning its output.
+fn run(bin: &str, args: &[&Path]) -> String {
+    let output = Command::new(bin)
+        .args(args)
+        .arg("--quiet")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{} {:?} failed", bin, args);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// The hash following `prefix` in `stdout`.
+fn find_hash(stdout: &str, prefix: &str) -> [u8; 64] {
+    let line = stdout.lines().find_map(|l| l.strip_prefix(prefix)).unwrap();
+    parse_hash(line.split_whitespace().next().unwrap()).unwrap()
+}
+
+/// A ceremony on test circuits run through the binaries that don't need the
+/// MASP circuits, checking their output against the library.
+#[test]
+fn simulated_ceremony() {
+    let dir = test_dir("ceremony");
+    let round = |i: usize| dir.join(format!("round{}.params", i));
+    let allow = Path::new(UnknownCircuits::ALLOW_FLAG);
+
+    // new
+    let mut ceremony = Ceremony {
+        circuits: vec![],
+        history: vec![],
+        encoding: Encoding::Uncompressed,
+    };
+    for name in &["a", "b"] {
+        ceremony.circuits.push(CircuitParams {
+            name: name.to_string(),
+            fingerprint: None,
+            params: MPCParameters::new(TestCircuit { x: None }).unwrap(),
+        });
+    }
+    let mut digests = vec![write(&ceremony, &round(0))];
+
+    // contribute, the second round through a delta
+    let mut hashes = vec![];
+    for i in 1..=CONTRIBUTIONS {
+        let entropy = format!("entropy {}", i);
+        let stdout = if i == 2 {
+            let delta = dir.join("round2.delta");
+            let stdout = run(
+                env!("CARGO_BIN_EXE_contribute"),
+                &[
+                    &round(i - 1),
+                    &delta,
+                    Path::new(&entropy),
+                    Path::new("--delta"),
+                    allow,
+                ],
+            );
+            let applied = run(
+                env!("CARGO_BIN_EXE_apply_delta"),
+                &[&round(i - 1), &delta, &round(i), allow],
+            );
+            assert_eq!(
+                find_hash(&applied, "Contribution hash: "),
+                find_hash(&stdout, "Contribution hash: ")
+            );
+            applied
+        } else {
+            run(
+                env!("CARGO_BIN_EXE_contribute"),
+                &[&round(i - 1), &round(i), Path::new(&entropy), allow],
+            )
+        };
+        hashes.push(find_hash(&stdout, "Contribution hash: "));
+        let digest = find_hash(&stdout, "File digest: ");
+        assert_eq!(digest, file_digest(round(i)).unwrap());
+        digests.push(digest);
+    }
+    let last = CONTRIBUTIONS;
+
+    // verify
+    let last_ceremony = read(&round(last), true);
+    assert_eq!(last_ceremony.history, digests[..last]);
+    let mut contributions = vec![];
+    for circuit in &last_ceremony.circuits {
+        let verified = circuit.params.verify(TestCircuit { x: None }).unwrap();
+        assert_eq!(
+            batch::verify(&circuit.params, TestCircuit { x: None }).unwrap(),
+            verified
+        );
+        contributions.push(verified);
+    }
+    assert_eq!(contributions, last_ceremony.contributions());
+    assert_eq!(combined_hashes(&contributions).unwrap(), hashes);
+
+    // verify_transform
+    for i in 1..=last {
+        let stdout = run(
+            env!("CARGO_BIN_EXE_verify_transform"),
+            &[&round(i - 1), &round(i), allow],
+        );
+        assert_eq!(parse_hash(&stdout), Some(hashes[i - 1]));
+    }
+    let first = read(&round(0), false);
+    for (before, after) in first.circuits.iter().zip(&last_ceremony.circuits) {
+        assert!(verify_contribution(&before.params, &after.params).is_err());
+    }
+
+    // Each round's public keys are in the final file at that round, which was
+    // contributed to the file with the recorded digest
+    for i in 1..=last {
+        let contributed = read(&round(i), false).contributions();
+        for (mine, all) in contributed.iter().zip(&contributions) {
+            assert_eq!(mine[..], all[..i]);
+        }
+        assert_eq!(
+            last_ceremony.history[i - 1],
+            file_digest(round(i - 1)).unwrap()
+        );
+    }
+
+    // Without the registry, unknown circuits are refused
+    let output = Command::new(env!("CARGO_BIN_EXE_verify_transform"))
+        .args([&round(0), &round(1)])
+        .arg("--quiet")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    // split_params, then join_params
+    let out_dir = dir.join("split");
+    fs::create_dir(&out_dir).unwrap();
+    // Copying the bytes as they are still needs the flag
+    let skip = Path::new("--skip-point-checks");
+    for (bin, args) in [
+        (
+            env!("CARGO_BIN_EXE_split_params"),
+            vec![&round(last), Path::new("--out-dir"), &out_dir, skip],
+        ),
+        (
+            env!("CARGO_BIN_EXE_export"),
+            vec![&round(last), &out_dir, skip],
+        ),
+    ] {
+        let output = Command::new(bin)
+            .args(args)
+            .arg("--quiet")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains(UnknownCircuits::ALLOW_FLAG));
+    }
+    assert_eq!(fs::read_dir(&out_dir).unwrap().count(), 0);
+    run(
+        env!("CARGO_BIN_EXE_split_params"),
+        &[&round(last), Path::new("--out-dir"), &out_dir, allow],
+    );
+    let file_layout = FileLayout::scan(BufReader::new(File::open(round(last)).unwrap())).unwrap();
+    assert_eq!(file_layout.header, last_ceremony.header());
+    let mut joined_args = vec![dir.join("joined.params")];
+    for ((circuit, circuit_layout), expected) in last_ceremony
+        .circuits
+        .iter()
+        .zip(&file_layout.circuits)
+        .zip(&contributions)
+    {
+        let path = out_dir.join(format!("masp-{}.params", circuit.name));
+        let len = fs::metadata(&path).unwrap().len();
+        assert_eq!(len, layout::serialized_len(&circuit.params));
+        assert_eq!(len, circuit_layout.end() - circuit_layout.start);
+
+        let split = MPCParameters::read(BufReader::new(File::open(&path).unwrap()), true).unwrap();
+        assert_eq!(&split.verify(TestCircuit { x: None }).unwrap(), expected);
+        joined_args.push(format!("{}={}", circuit.name, path.display()).into());
+    }
+    let manifest = fs::read_to_string(out_dir.join("masp-manifest.txt")).unwrap();
+    assert_eq!(manifest.lines().count(), 2);
+
+    joined_args.push(allow.into());
+    let joined_args: Vec<&Path> = joined_args.iter().map(PathBuf::as_path).collect();
+    run(env!("CARGO_BIN_EXE_join_params"), &joined_args);
+    let joined = read(&dir.join("joined.params"), true);
+    assert_eq!(joined.contributions(), contributions);
+
+    // export writes next to split_params' files without replacing them, and
+    // refuses to overwrite its own without --force
+    let stdout = run(
+        env!("CARGO_BIN_EXE_export"),
+        &[&round(last), &out_dir, allow],
+    );
+    for circuit in &last_ceremony.circuits {
+        let mut groth16 = vec![];
+        circuit.params.get_params().write(&mut groth16).unwrap();
+        let path = out_dir.join(format!("masp-{}.groth16.params", circuit.name));
+        assert_eq!(fs::read(&path).unwrap(), groth16);
+        assert!(stdout.contains(&into_hex(&file_digest(&path).unwrap())));
+        let split = out_dir.join(format!("masp-{}.params", circuit.name));
+        assert_eq!(
+            fs::metadata(split).unwrap().len(),
+            layout::serialized_len(&circuit.params)
+        );
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_export"))
+        .args([&round(last), &out_dir, allow])
+        .arg("--quiet")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    run(
+        env!("CARGO_BIN_EXE_export"),
+        &[&round(last), &out_dir, allow, Path::new("--force")],
+    );
+
+    // inspect lists every contribution of every circuit
+    let stdout = run(env!("CARGO_BIN_EXE_inspect"), &[&round(last)]);
+    assert_eq!(
+        find_hash(&stdout, "File digest:   "),
+        file_digest(round(last)).unwrap()
+    );
+    for hash in contributions.iter().flatten() {
+        assert!(stdout.contains(&into_hex(hash)));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The same ceremony on the MASP circuits, run through the binaries. Creating
+/// and verifying the parameters takes a long time, so it only runs when asked
+/// for with `cargo test --all-features -- --ignored`.
+#[cfg(all(feature = "verification", feature = "beacon"))]
+#[test]
+#[ignore]
+fn masp_ceremony() {
+    use masp_mpc::mmap::MappedCeremony;
+
+    let dir = test_dir("masp-ceremony");
+    let round = |i: usize| dir.join(format!("round{}.params", i));
+    let radix = Path::new(".");
+
+    run(env!("CARGO_BIN_EXE_new"), &[&round(0), radix]);
+
+    let mut hashes = vec![];
+    for i in 1..=CONTRIBUTIONS + 1 {
+        let stdout = if i == 2 {
+            // Uploading only the delta gives the same file
+            let delta = dir.join("round2.delta");
+            let stdout = run(
+                env!("CARGO_BIN_EXE_contribute"),
+                &[
+                    &round(1),
+                    &delta,
+                    Path::new("entropy 2"),
+                    Path::new("--delta"),
+                ],
+            );
+            assert!(fs::metadata(&delta).unwrap().len() < fs::metadata(round(1)).unwrap().len());
+            let applied = run(
+                env!("CARGO_BIN_EXE_apply_delta"),
+                &[&round(1), &delta, &round(2)],
+            );
+            assert_eq!(
+                find_hash(&applied, "Contribution hash: "),
+                find_hash(&stdout, "Contribution hash: ")
+            );
+            applied
+        } else if i <= CONTRIBUTIONS {
+            let entropy = format!("entropy {}", i);
+            run(
+                env!("CARGO_BIN_EXE_contribute"),
+                &[&round(i - 1), &round(i), Path::new(&entropy)],
+            )
+        } else {
+            run(env!("CARGO_BIN_EXE_beacon"), &[&round(i - 1), &round(i)])
+        };
+        hashes.push(find_hash(&stdout, "Contribution hash: "));
+        assert_eq!(
+            find_hash(&stdout, "File digest: "),
+            file_digest(round(i)).unwrap()
+        );
+    }
+    let last = CONTRIBUTIONS + 1;
+
+    let verified: Vec<[u8; 64]> = run(env!("CARGO_BIN_EXE_verify"), &[&round(last), radix])
+        .lines()
+        .map(|line| parse_hash(line).unwrap())
+        .collect();
+    assert_eq!(verified, hashes);
+
+    for i in 1..=last {
+        let stdout = run(
+            env!("CARGO_BIN_EXE_verify_transform"),
+            &[&round(i - 1), &round(i)],
+        );
+        assert_eq!(parse_hash(&stdout), Some(hashes[i - 1]));
+
+        let hash = into_hex(&hashes[i - 1]);
+        let stdout = run(
+            env!("CARGO_BIN_EXE_verify_contribution"),
+            &[Path::new(&hash), &round(last)],
+        );
+        assert!(stdout.contains(&format!("found at round {}", i)));
+        // Only the final file's digest is computed, the others come from its
+        // history
+        assert_eq!(stdout.contains("unverified"), i < last);
+        assert_eq!(
+            find_hash(&stdout, "  File digest:       "),
+            file_digest(round(i)).unwrap()
+        );
+    }
+
+    run(env!("CARGO_BIN_EXE_smoke_test"), &[&round(last)]);
+
+    // Compressing changes nothing but the encoding
+    let compressed = dir.join("compressed.params");
+    let decompressed = dir.join("decompressed.params");
+    run(
+        env!("CARGO_BIN_EXE_compress_params"),
+        &[&round(last), &compressed],
+    );
+    assert!(fs::metadata(&compressed).unwrap().len() < fs::metadata(round(last)).unwrap().len());
+    let stdout = run(env!("CARGO_BIN_EXE_verify"), &[&compressed, radix]);
+    assert_eq!(stdout.lines().count(), hashes.len());
+    run(
+        env!("CARGO_BIN_EXE_decompress_params"),
+        &[&compressed, &decompressed],
+    );
+    assert_eq!(
+        fs::read(&decompressed).unwrap(),
+        fs::read(round(last)).unwrap()
+    );
+
+    let out_dir = dir.join("split");
+    fs::create_dir(&out_dir).unwrap();
+    run(
+        env!("CARGO_BIN_EXE_split_params"),
+        &[&round(last), Path::new("--out-dir"), &out_dir],
+    );
+    let manifest = fs::read_to_string(out_dir.join("masp-manifest.txt")).unwrap();
+    for line in manifest.lines() {
+        let fields: Vec<&str> = line.split(' ').collect();
+        let path = out_dir.join(fields[0]);
+        assert_eq!(fs::metadata(&path).unwrap().len().to_string(), fields[1]);
+        assert_eq!(parse_hash(fields[2]), Some(file_digest(&path).unwrap()));
+    }
+    assert_eq!(manifest.lines().count(), 3);
+
+    // inspect lists the history and every contribution of every circuit
+    let stdout = run(env!("CARGO_BIN_EXE_inspect"), &[&round(last)]);
+    assert_eq!(
+        find_hash(&stdout, "File digest:   "),
+        file_digest(round(last)).unwrap()
+    );
+    for i in 0..last {
+        assert!(stdout.contains(&into_hex(&file_digest(round(i)).unwrap())));
+    }
+    let mapped = MappedCeremony::open(round(last)).unwrap();
+    for hash in mapped.contributions().iter().flatten() {
+        assert!(stdout.contains(&into_hex(hash)));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Fetches a chunked params file from a local HTTP server that drops one
+/// chunk halfway and fails another, next to chunks already downloaded or
+/// damaged, then assembles it.
+#[cfg(feature = "fetch")]
+#[test]
+fn chunked_fetch() {
+    use masp_mpc::chunks::{self, Manifest};
+    use masp_mpc::progress::Mode;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use std::collections::HashMap;
+    use std::io::{BufRead, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Copy)]
+    enum Fault {
+        /// Closes the connection halfway through the body.
+        Drop,
+        Error,
+    }
+
+    let dir = test_dir("fetch");
+    let server_dir = dir.join("server");
+    let client_dir = dir.join("client");
+    fs::create_dir(&server_dir).unwrap();
+    fs::create_dir(&client_dir).unwrap();
+
+    let mut ceremony = Ceremony {
+        circuits: vec![],
+        history: vec![],
+        encoding: Encoding::Compressed,
+    };
+    let mut rng = ChaChaRng::from_seed([0u8; 32]);
+    for name in &["a", "b"] {
+        let mut params = MPCParameters::new(TestCircuit { x: None }).unwrap();
+        params.contribute(&mut rng, &0);
+        ceremony.circuits.push(CircuitParams {
+            name: name.to_string(),
+            fingerprint: None,
+            params,
+        });
+    }
+    let path = server_dir.join("round1.params");
+    let digest = write(&ceremony, &path);
+    let manifest = chunks::split(Mode::Quiet, &path, &server_dir, 1000, false).unwrap();
+    assert!(manifest.chunks.len() >= 4);
+
+    // A previous run got the first chunk, and the fourth one wrong
+    let chunk = |i: usize| server_dir.join(manifest.chunk_name(i));
+    fs::copy(chunk(0), client_dir.join(manifest.chunk_name(0))).unwrap();
+    fs::write(client_dir.join(manifest.chunk_name(3)), vec![0u8; 1000]).unwrap();
+
+    let faults: HashMap<String, Fault> = vec![
+        (manifest.chunk_name(1), Fault::Drop),
+        (manifest.chunk_name(2), Fault::Error),
+    ]
+    .into_iter()
+    .collect();
+    let faults = Arc::new(Mutex::new(faults));
+    let requests = Arc::new(Mutex::new(vec![]));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!(
+        "http://{}/{}",
+        listener.local_addr().unwrap(),
+        Manifest::file_name(&manifest.name)
+    );
+    {
+        let requests = requests.clone();
+        let faults = faults.clone();
+        let server_dir = server_dir.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let name = line.split(' ').nth(1).unwrap()[1..].to_string();
+                let mut start = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    let header = header.to_lowercase();
+                    if let Some(range) = header.strip_prefix("range: bytes=") {
+                        start = range.trim().trim_end_matches('-').parse().unwrap();
+                    }
+                }
+                requests.lock().unwrap().push((name.clone(), start));
+
+                let fault = faults.lock().unwrap().remove(&name);
+                if let Some(Fault::Error) = fault {
+                    write!(
+                        stream,
+                        "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    )
+                    .unwrap();
+                    continue;
+                }
+                let mut body = vec![];
+                File::open(server_dir.join(&name))
+                    .unwrap()
+                    .read_to_end(&mut body)
+                    .unwrap();
+                let len = body.len();
+                let status = match start {
+                    0 => "200 OK".to_string(),
+                    _ => format!(
+                        "206 Partial Content\r\nContent-Range: bytes {}-{}/{}",
+                        start,
+                        len - 1,
+                        len
+                    ),
+                };
+                let body = &body[start..];
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .unwrap();
+                match fault {
+                    Some(Fault::Drop) => stream.write_all(&body[..body.len() / 2]).unwrap(),
+                    _ => stream.write_all(body).unwrap(),
+                }
+            }
+        });
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_fetch"))
+        .arg(&url)
+        .arg("--out-dir")
+        .arg(&client_dir)
+        .arg("--quiet")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "fetch failed");
+
+    // The dropped chunk resumes where it stopped, the failed one is retried
+    let requests = requests.lock().unwrap().clone();
+    let requested = |i: usize| -> Vec<usize> {
+        let name = manifest.chunk_name(i);
+        requests
+            .iter()
+            .filter(|(n, _)| n == &name)
+            .map(|(_, start)| *start)
+            .collect()
+    };
+    assert_eq!(requested(0), vec![]);
+    assert_eq!(requested(1), vec![0, 500]);
+    assert_eq!(requested(2), vec![0, 0]);
+    assert_eq!(requested(3), vec![0]);
+
+    let out = dir.join("round1.params");
+    let output = Command::new(env!("CARGO_BIN_EXE_assemble"))
+        .arg(client_dir.join(Manifest::file_name(&manifest.name)))
+        .arg(&out)
+        .arg("--quiet")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "assemble failed");
+    assert_eq!(file_digest(&out).unwrap(), digest);
+    assert_eq!(read(&out, true).header(), ceremony.header());
+
+    fs::remove_dir_all(&dir).unwrap();
+}